@@ -0,0 +1,111 @@
+//! Lookup throughput of a single namespace as the number of concurrent callers grows.
+//!
+//! Every caller runs on its own thread with its own runtime, the same way gRPC
+//! handlers end up spread over the tokio workers. Lookups don't take any lock, so
+//! throughput should scale with the number of cores until callers outnumber them.
+//!
+//! Run with `cargo bench --bench lookup`.
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use ulok::core::{
+    application::service_discovery::ServiceDiscovery, domain::server::ServiceDiscoveryUsecase,
+    schema::NamespaceDefinition,
+};
+use ulok::pool::consistent_hash::build;
+
+const NAMESPACE: &str = "payment";
+const NODES: u32 = 64;
+const CALLERS: [usize; 4] = [1, 2, 4, 8];
+
+fn runtime() -> tokio::runtime::Runtime {
+    tokio::runtime::Builder::new_current_thread()
+        .build()
+        .expect("failed to build runtime")
+}
+
+/// A namespace of `NODES` registered agents, 100 points each.
+fn registry() -> Arc<ServiceDiscovery> {
+    let definition = NamespaceDefinition {
+        virtual_nodes: 100,
+        ..Default::default()
+    };
+    let mut services = HashMap::new();
+    services.insert(
+        NAMESPACE.to_string(),
+        build(NAMESPACE.to_string(), definition),
+    );
+    let service = Arc::new(ServiceDiscovery::new(services));
+
+    runtime().block_on(async {
+        for i in 0..NODES {
+            service
+                .register_node(
+                    NAMESPACE.to_string(),
+                    format!("10.0.0.{}", i),
+                    8080,
+                    HashMap::new(),
+                    "bench".to_string(),
+                )
+                .await
+                .expect("failed to register node");
+        }
+    });
+
+    service
+}
+
+fn lookup(c: &mut Criterion) {
+    let service = registry();
+    let mut group = c.benchmark_group("lookup");
+
+    for callers in CALLERS {
+        group.throughput(Throughput::Elements(callers as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(callers),
+            &callers,
+            |b, &callers| {
+                // One iteration is one lookup per caller, all of them running at once
+                b.iter_custom(|iters| {
+                    let handles: Vec<thread::JoinHandle<Duration>> = (0..callers)
+                        .map(|caller| {
+                            let service = service.clone();
+                            thread::spawn(move || {
+                                let rt = runtime();
+                                let start = Instant::now();
+                                rt.block_on(async {
+                                    for i in 0..iters {
+                                        let client = format!("192.168.{}.{}", caller, i % 256);
+                                        let _ = service
+                                            .service_lookup(
+                                                NAMESPACE.to_string(),
+                                                client,
+                                                HashMap::new(),
+                                            )
+                                            .await;
+                                    }
+                                });
+                                start.elapsed()
+                            })
+                        })
+                        .collect();
+
+                    handles
+                        .into_iter()
+                        .map(|handle| handle.join().expect("caller panicked"))
+                        .max()
+                        .unwrap_or_default()
+                });
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, lookup);
+criterion_main!(benches);
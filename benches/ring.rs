@@ -0,0 +1,111 @@
+//! Cost of the ring operations as namespaces grow, lookups should stay
+//! logarithmic in the number of points and a health flip shouldn't copy the
+//! metadata of every server.
+//!
+//! Run with `cargo bench --bench ring`.
+use std::collections::HashMap;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use ulok::core::schema::NamespaceDefinition;
+use ulok::pool::consistent_hash::{build, Ring};
+use ulok::pool::pool::NodePool;
+
+const VIRTUAL_NODES: u32 = 64;
+const SERVERS: [u32; 3] = [10, 100, 1000];
+/// Labels of every server, what a ring copying its servers would pay for.
+const LABELS: u32 = 8;
+
+fn address(i: u32) -> String {
+    format!("10.{}.{}.{}", i >> 16 & 0xff, i >> 8 & 0xff, i & 0xff)
+}
+
+fn labels() -> HashMap<String, String> {
+    (0..LABELS)
+        .map(|i| (format!("label-{}", i), format!("value-{}", i)))
+        .collect()
+}
+
+/// A ring of `servers` labelled agents, every tenth of them unhealthy.
+fn ring(servers: u32) -> Ring {
+    let definition = NamespaceDefinition {
+        virtual_nodes: VIRTUAL_NODES,
+        ..Default::default()
+    };
+    let ring = build("payment".to_string(), definition);
+
+    for i in 0..servers {
+        let service_id = ring
+            .add_server(format!("agent-{}", i), address(i), 8080, labels(), None)
+            .expect("failed to add server");
+        if i % 10 == 0 {
+            ring.set_health_status(service_id, false)
+                .expect("failed to flip health");
+        }
+    }
+
+    ring
+}
+
+fn get(c: &mut Criterion) {
+    let mut group = c.benchmark_group("ring/get");
+
+    for servers in SERVERS {
+        let ring = ring(servers);
+        let selector = HashMap::new();
+        let mut client = 0u32;
+        group.bench_with_input(BenchmarkId::from_parameter(servers), &ring, |b, ring| {
+            b.iter(|| {
+                client = client.wrapping_add(1);
+                let client_ip = format!("192.168.{}.{}", client >> 8 & 0xff, client & 0xff);
+                black_box(ring.get(client_ip, &selector))
+            });
+        });
+    }
+
+    group.finish();
+}
+
+fn set_health_status(c: &mut Criterion) {
+    let mut group = c.benchmark_group("ring/set_health_status");
+
+    for servers in SERVERS {
+        let ring = ring(servers);
+        let mut healthy = false;
+        group.bench_with_input(BenchmarkId::from_parameter(servers), &ring, |b, ring| {
+            b.iter(|| {
+                healthy = !healthy;
+                ring.set_health_status("agent-1".to_string(), healthy)
+            });
+        });
+    }
+
+    group.finish();
+}
+
+fn add_server(c: &mut Criterion) {
+    let mut group = c.benchmark_group("ring/add_server");
+
+    for servers in SERVERS {
+        let ring = ring(servers);
+        group.bench_with_input(BenchmarkId::from_parameter(servers), &ring, |b, ring| {
+            b.iter(|| {
+                let service_id = ring
+                    .add_server(
+                        "newcomer".to_string(),
+                        address(servers),
+                        8080,
+                        HashMap::new(),
+                        None,
+                    )
+                    .expect("failed to add server");
+                ring.remove_server(service_id)
+            });
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, get, set_health_status, add_server);
+criterion_main!(benches);
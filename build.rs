@@ -0,0 +1,11 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR")?);
+    // Only the descriptors are used, served by gRPC reflection. The bindings
+    // are checked in as src/grpc.rs
+    tonic_prost_build::configure()
+        .build_server(false)
+        .build_client(false)
+        .file_descriptor_set_path(out_dir.join("horbo_descriptor.bin"))
+        .compile_protos(&["proto/messages.proto"], &["proto"])?;
+    Ok(())
+}
@@ -2,6 +2,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     tonic_prost_build::configure()
         .build_server(true)
         .build_client(true)
-        .compile_protos(&["messages.proto"], &["proto"])?;
+        // Served as JSON by the HTTP API as well
+        .message_attribute(".", "#[derive(serde::Serialize)]")
+        .compile_protos(&["messages.proto"], &["."])?;
     Ok(())
 }
@@ -0,0 +1,36 @@
+use std::sync::Arc;
+
+use tonic::{Request, Status};
+
+use crate::auth::policy::{AuthPolicy, Caller};
+
+/// Metadata key agents send their API key in, on any call.
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Identifies the caller of every gRPC call and attaches it as a `Caller`
+/// extension. Namespaces live in request bodies, which interceptors can't see,
+/// so the controller authorizes each call against it.
+pub fn authenticate(
+    policy: Arc<AuthPolicy>,
+) -> impl FnMut(Request<()>) -> Result<Request<()>, Status> + Clone {
+    move |mut request: Request<()>| {
+        if !policy.enabled() {
+            return Ok(request);
+        }
+
+        let api_key = match request.metadata().get(API_KEY_HEADER) {
+            Some(value) => match value.to_str() {
+                Ok(key) => Some(key.to_string()),
+                Err(_) => return Err(Status::unauthenticated("api key isn't valid ascii")),
+            },
+            None => None,
+        };
+
+        let certs = request.peer_certs();
+        let caller: Caller =
+            policy.identify(certs.as_deref().map(Vec::as_slice), api_key.as_deref())?;
+        request.extensions_mut().insert(caller);
+
+        Ok(request)
+    }
+}
@@ -0,0 +1,2 @@
+pub mod interceptor;
+pub mod policy;
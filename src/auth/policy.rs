@@ -0,0 +1,389 @@
+use serde::Deserialize;
+use tokio_rustls::rustls::pki_types::CertificateDer;
+use tonic::Status;
+use x509_parser::prelude::{FromDer, GeneralName, X509Certificate};
+
+use crate::core::schema::{AuthDefinition, PrincipalDefinition};
+
+/// Namespace entry of a principal granting every namespace.
+pub const ANY_NAMESPACE: &str = "*";
+
+/// What a caller is trying to do, as granted to principals in `auth.principals`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    /// Register, heartbeat and deregister agents.
+    Register,
+    Lookup,
+    ReportFailure,
+    /// Create, list, describe and delete namespaces.
+    Admin,
+}
+
+/// Principals a call was identified as, attached to the request by the interceptor.
+#[derive(Debug, Clone, Default)]
+pub struct Caller {
+    principals: Vec<usize>,
+    /// The credential that matched, `cert:<name>` or `key:<principal>/<n>`. Many
+    /// clients may share a principal, this tells them apart.
+    identity: Option<String>,
+}
+
+impl Caller {
+    pub fn extend(&mut self, other: Caller) {
+        for principal in other.principals {
+            if !self.principals.contains(&principal) {
+                self.principals.push(principal);
+            }
+        }
+        if self.identity.is_none() {
+            self.identity = other.identity;
+        }
+    }
+}
+
+/// Maps client certificate names and API keys to the namespaces and operations
+/// they're allowed. Without an `auth:` block every call is allowed.
+#[derive(Debug, Default)]
+pub struct AuthPolicy {
+    enabled: bool,
+    principals: Vec<PrincipalDefinition>,
+}
+
+/// Whether `principal` is granted `namespace`, explicitly or through `*`.
+fn covers(principal: &PrincipalDefinition, namespace: &str) -> bool {
+    principal
+        .namespaces
+        .iter()
+        .any(|allowed| allowed == ANY_NAMESPACE || allowed == namespace)
+}
+
+/// Compares without bailing out on the first mismatching byte.
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// DNS and URI subject alternative names of a certificate, then its common names.
+fn certificate_names(cert: &CertificateDer) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    let cert = match X509Certificate::from_der(cert.as_ref()) {
+        Ok((_, cert)) => cert,
+        Err(_) => return names,
+    };
+
+    if let Ok(Some(san)) = cert.subject_alternative_name() {
+        for name in san.value.general_names.iter() {
+            match name {
+                GeneralName::DNSName(dns) => names.push(dns.to_string()),
+                GeneralName::URI(uri) => names.push(uri.to_string()),
+                _ => {}
+            }
+        }
+    }
+    for cn in cert.subject().iter_common_name() {
+        if let Ok(cn) = cn.as_str() {
+            names.push(cn.to_string());
+        }
+    }
+
+    names
+}
+
+impl AuthPolicy {
+    pub fn new(definition: Option<&AuthDefinition>) -> Self {
+        match definition {
+            Some(definition) => AuthPolicy {
+                enabled: true,
+                principals: definition.principals.clone(),
+            },
+            None => AuthPolicy::default(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Resolves the principals behind a client certificate chain and an API key.
+    ///
+    /// A key that matches no principal is rejected outright rather than ignored,
+    /// so a typo doesn't silently fall back to the certificate identity. Without
+    /// auth nothing is checked and every caller is anonymous.
+    pub fn identify(
+        &self,
+        certs: Option<&[CertificateDer]>,
+        api_key: Option<&str>,
+    ) -> Result<Caller, Status> {
+        let mut caller = Caller::default();
+        if !self.enabled {
+            return Ok(caller);
+        }
+
+        /* Only the leaf identifies the client, the rest of the chain are its issuers */
+        if let Some(leaf) = certs.and_then(|certs| certs.first()) {
+            let names = certificate_names(leaf);
+            for (i, principal) in self.principals.iter().enumerate() {
+                if let Some(name) = names
+                    .iter()
+                    .find(|name| principal.cert_names.contains(name))
+                {
+                    caller.principals.push(i);
+                    caller
+                        .identity
+                        .get_or_insert_with(|| format!("cert:{}", name));
+                }
+            }
+        }
+
+        if let Some(api_key) = api_key {
+            let mut known = false;
+            for (i, principal) in self.principals.iter().enumerate() {
+                let matched = principal
+                    .api_keys
+                    .iter()
+                    .position(|key| constant_time_eq(key.as_bytes(), api_key.as_bytes()));
+                if let Some(n) = matched {
+                    known = true;
+                    caller.extend(Caller {
+                        principals: vec![i],
+                        identity: Some(format!("key:{}/{}", principal.name, n + 1)),
+                    });
+                }
+            }
+            if !known {
+                return Err(Status::unauthenticated("unknown api key"));
+            }
+        }
+
+        Ok(caller)
+    }
+
+    /// The certificate name or API key a caller was identified by, so clients
+    /// sharing a principal still count as distinct. `None` without auth, where
+    /// callers have no identity of their own.
+    pub fn identity(&self, caller: &Caller) -> Option<String> {
+        match self.enabled {
+            true => caller.identity.clone(),
+            false => None,
+        }
+    }
+
+    /// Whether one of the caller's principals may perform `operation` on `namespace`,
+    /// to narrow answers spanning every namespace down to the caller's. Always true
+    /// without auth.
+    pub fn allows(&self, caller: &Caller, namespace: &str, operation: Operation) -> bool {
+        !self.enabled
+            || caller.principals.iter().any(|i| {
+                let principal = &self.principals[*i];
+                principal.operations.contains(&operation) && covers(principal, namespace)
+            })
+    }
+
+    /// Same as `allows`, for any operation.
+    pub fn grants(&self, caller: &Caller, namespace: &str) -> bool {
+        !self.enabled
+            || caller
+                .principals
+                .iter()
+                .any(|i| covers(&self.principals[*i], namespace))
+    }
+
+    /// Checks that one of the caller's principals may perform `operation` on
+    /// `namespace`, `None` checks the operation alone, e.g. listing namespaces.
+    pub fn authorize(
+        &self,
+        caller: &Caller,
+        namespace: Option<&str>,
+        operation: Operation,
+    ) -> Result<(), Status> {
+        if !self.enabled {
+            return Ok(());
+        }
+        if caller.principals.is_empty() {
+            return Err(Status::unauthenticated(
+                "no client certificate or api key matches a principal",
+            ));
+        }
+
+        let allowed = caller.principals.iter().any(|i| {
+            let principal = &self.principals[*i];
+            principal.operations.contains(&operation)
+                && namespace.is_none_or(|namespace| covers(principal, namespace))
+        });
+
+        match allowed {
+            true => Ok(()),
+            false => Err(Status::permission_denied(format!(
+                "{:?} is not allowed on namespace {}",
+                operation,
+                namespace.unwrap_or(ANY_NAMESPACE)
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rcgen::{CertificateParams, DnType, KeyPair};
+    use tonic::Code;
+
+    fn principal(
+        name: &str,
+        cert_names: &[&str],
+        api_keys: &[&str],
+        namespaces: &[&str],
+        operations: &[Operation],
+    ) -> PrincipalDefinition {
+        let strings = |items: &[&str]| items.iter().map(|item| item.to_string()).collect();
+        PrincipalDefinition {
+            name: name.to_string(),
+            cert_names: strings(cert_names),
+            api_keys: strings(api_keys),
+            namespaces: strings(namespaces),
+            operations: operations.to_vec(),
+        }
+    }
+
+    /// `payment-agent` registers in `payment` by certificate, `gateway` looks up
+    /// anywhere with either of two keys.
+    fn policy() -> AuthPolicy {
+        AuthPolicy::new(Some(&AuthDefinition {
+            principals: vec![
+                principal(
+                    "payment-agent",
+                    &["payment.internal"],
+                    &[],
+                    &["payment"],
+                    &[Operation::Register],
+                ),
+                principal(
+                    "gateway",
+                    &["gateway"],
+                    &["k1", "k2"],
+                    &[ANY_NAMESPACE],
+                    &[Operation::Lookup],
+                ),
+            ],
+        }))
+    }
+
+    /// A self-signed certificate with `san` as DNS name and `cn` as common name.
+    fn certificate(san: &str, cn: &str) -> CertificateDer<'static> {
+        let mut params = CertificateParams::new(vec![san.to_string()]).unwrap();
+        params.distinguished_name.push(DnType::CommonName, cn);
+        let key = KeyPair::generate().unwrap();
+        params.self_signed(&key).unwrap().der().clone()
+    }
+
+    #[test]
+    fn identifies_certificates_by_san_then_cn() {
+        let policy = policy();
+        let by_san = policy
+            .identify(Some(&[certificate("payment.internal", "agent-7")]), None)
+            .unwrap();
+        assert_eq!(by_san.principals, vec![0]);
+        assert_eq!(policy.identity(&by_san).unwrap(), "cert:payment.internal");
+
+        let by_cn = policy
+            .identify(Some(&[certificate("other.internal", "gateway")]), None)
+            .unwrap();
+        assert_eq!(by_cn.principals, vec![1]);
+        assert_eq!(policy.identity(&by_cn).unwrap(), "cert:gateway");
+    }
+
+    #[test]
+    fn only_the_leaf_certificate_identifies_the_client() {
+        let chain = [
+            certificate("stranger.internal", "stranger"),
+            certificate("payment.internal", "payment issuer"),
+        ];
+        let caller = policy().identify(Some(&chain), None).unwrap();
+        assert!(caller.principals.is_empty());
+        assert!(caller.identity.is_none());
+    }
+
+    #[test]
+    fn tells_keys_of_one_principal_apart() {
+        let policy = policy();
+        let first = policy.identify(None, Some("k1")).unwrap();
+        let second = policy.identify(None, Some("k2")).unwrap();
+        assert_eq!(first.principals, vec![1]);
+        assert_eq!(policy.identity(&first).unwrap(), "key:gateway/1");
+        assert_eq!(policy.identity(&second).unwrap(), "key:gateway/2");
+    }
+
+    #[test]
+    fn rejects_unknown_api_keys() {
+        let certs = [certificate("payment.internal", "agent-7")];
+        let e = policy().identify(Some(&certs), Some("typo")).unwrap_err();
+        assert_eq!(e.code(), Code::Unauthenticated);
+    }
+
+    #[test]
+    fn a_key_adds_to_the_certificate_principals() {
+        let certs = [certificate("payment.internal", "agent-7")];
+        let caller = policy().identify(Some(&certs), Some("k1")).unwrap();
+        assert_eq!(caller.principals, vec![0, 1]);
+        assert_eq!(caller.identity.unwrap(), "cert:payment.internal");
+    }
+
+    #[test]
+    fn authorizes_operations_on_granted_namespaces() {
+        let policy = policy();
+        let agent = policy
+            .identify(Some(&[certificate("payment.internal", "agent-7")]), None)
+            .unwrap();
+        assert!(policy
+            .authorize(&agent, Some("payment"), Operation::Register)
+            .is_ok());
+        let e = policy
+            .authorize(&agent, Some("booking"), Operation::Register)
+            .unwrap_err();
+        assert_eq!(e.code(), Code::PermissionDenied);
+        let e = policy
+            .authorize(&agent, Some("payment"), Operation::Admin)
+            .unwrap_err();
+        assert_eq!(e.code(), Code::PermissionDenied);
+
+        let e = policy
+            .authorize(&Caller::default(), None, Operation::Lookup)
+            .unwrap_err();
+        assert_eq!(e.code(), Code::Unauthenticated);
+    }
+
+    #[test]
+    fn allows_and_grants_follow_the_principals() {
+        let policy = policy();
+        let gateway = policy.identify(None, Some("k2")).unwrap();
+        assert!(policy.allows(&gateway, "booking", Operation::Lookup));
+        assert!(!policy.allows(&gateway, "booking", Operation::Register));
+        assert!(policy.grants(&gateway, "booking"));
+
+        let agent = policy
+            .identify(Some(&[certificate("payment.internal", "agent-7")]), None)
+            .unwrap();
+        assert!(policy.grants(&agent, "payment"));
+        assert!(!policy.grants(&agent, "booking"));
+    }
+
+    #[test]
+    fn covers_listed_namespaces_or_any_with_a_wildcard() {
+        let listed = principal("a", &[], &[], &["payment"], &[]);
+        assert!(covers(&listed, "payment"));
+        assert!(!covers(&listed, "booking"));
+        assert!(covers(&principal("b", &[], &[], &["*"], &[]), "booking"));
+    }
+
+    #[test]
+    fn everything_is_allowed_without_auth() {
+        let policy = AuthPolicy::new(None);
+        let caller = policy.identify(None, Some("anything")).unwrap();
+        assert!(policy
+            .authorize(&caller, Some("payment"), Operation::Admin)
+            .is_ok());
+        assert!(policy.allows(&caller, "payment", Operation::Admin));
+        assert!(policy.grants(&caller, "payment"));
+        assert!(policy.identity(&caller).is_none());
+    }
+}
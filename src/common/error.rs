@@ -1,10 +1,13 @@
 use std::fmt::Display;
 
-
 #[derive(Debug)]
 pub enum ErrorResponse {
     Internal(String),
     BadRequest(String),
+    /// The server is shutting down, the call may be retried on another instance.
+    Unavailable(String),
+    /// The caller may use the namespace, but not this particular node of it.
+    Forbidden(String),
 }
 
 impl Display for ErrorResponse {
@@ -12,6 +15,8 @@ impl Display for ErrorResponse {
         match self {
             ErrorResponse::Internal(err) => write!(f, "internal error: {}", err),
             ErrorResponse::BadRequest(err) => write!(f, "bad request: {}", err),
+            ErrorResponse::Unavailable(err) => write!(f, "unavailable: {}", err),
+            ErrorResponse::Forbidden(err) => write!(f, "forbidden: {}", err),
         }
     }
 }
@@ -21,6 +26,60 @@ impl std::error::Error for ErrorResponse {
         match self {
             ErrorResponse::Internal(_) => None,
             ErrorResponse::BadRequest(_) => None,
+            ErrorResponse::Unavailable(_) => None,
+            ErrorResponse::Forbidden(_) => None,
         }
     }
-}
\ No newline at end of file
+}
+
+/// A single invalid setting of the configuration file.
+#[derive(Debug)]
+pub struct ConfigProblem {
+    /// Dotted path of the setting, e.g. `services.payment.health.cpu_limit`.
+    pub field: String,
+    /// Line of the setting, or of its closest parent present in the file.
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl Display for ConfigProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}: {}", line, self.field, self.message),
+            None => write!(f, "{}: {}", self.field, self.message),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(String, std::io::Error),
+    Parse(String, serde_yaml::Error),
+    Invalid(String, Vec<ConfigProblem>),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ConfigError::Io(path, err) => write!(f, "can't read {}: {}", path, err),
+            ConfigError::Parse(path, err) => write!(f, "can't parse {}: {}", path, err),
+            ConfigError::Invalid(path, problems) => {
+                write!(f, "{} problem(s) in {}:", problems.len(), path)?;
+                for problem in problems {
+                    write!(f, "\n  {}", problem)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io(_, err) => Some(err),
+            ConfigError::Parse(_, err) => Some(err),
+            ConfigError::Invalid(_, _) => None,
+        }
+    }
+}
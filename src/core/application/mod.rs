@@ -1 +1,6 @@
-pub mod service_discovery;
\ No newline at end of file
+pub mod reaper;
+pub mod service_discovery;
+pub mod shutdown;
+pub mod snapshotter;
+pub mod threshold_policy;
+pub mod watcher;
@@ -0,0 +1,24 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::core::{
+    application::service_discovery::ServiceDiscovery, domain::server::ServiceDiscoveryUsecase,
+};
+
+/// How often namespaces are checked for silent nodes.
+pub const REAP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Background failure detector, marks silent nodes unhealthy and evicts the
+/// ones that stay silent past their namespace TTL. Runs until the runtime stops.
+pub async fn run(service: Arc<ServiceDiscovery>, every: Duration) {
+    let mut interval = tokio::time::interval(every);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+
+        if let Err(e) = service.reap_silent_nodes().await {
+            tracing::error!(error = %e, "failed to reap silent nodes");
+        }
+    }
+}
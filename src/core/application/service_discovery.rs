@@ -1,22 +1,372 @@
 use crate::common::error::ErrorResponse;
-use crate::grpc::{AgentRegistrationResponse, HeartbeatResponse, LookupResponse, NodeMap};
+use crate::grpc::{
+    AgentRegistrationResponse, HeartbeatResponse, ListInstancesResponse, ListNamespacesResponse,
+    LookupResponse, NamespaceDescription, NodeMap,
+};
 use crate::{
-    core::domain::{data::UtilizationMetric, server::ServiceDiscoveryUsecase},
-    pool::{consistent_hash::Ring, pool::NodePool},
+    core::application::watcher,
+    core::domain::{
+        data::UtilizationMetric, failure_report::FailureReports, server::ServiceDiscoveryUsecase,
+        unhealthy::UnhealthySet, watch::MembershipEvent,
+    },
+    core::schema::NamespaceDefinition,
+    core::validate::Validator,
+    pool::{
+        consistent_hash::{build, Ring},
+        pool::NodePool,
+    },
+    storage::storage::{NamespaceRecord, NodeRecord, Record, Snapshot, Storage},
+    telemetry::{logging::AUDIT, registry},
 };
-use std::collections::HashMap;
+use arc_swap::ArcSwap;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use tokio::sync::{mpsc, watch};
+use ulid::Ulid;
 
+/// Lookups only load the current namespace map and ring points, neither waits on
+/// writers. Writers lock the ring of the namespace they change, so namespaces don't
+/// contend with each other.
+///
+/// Lock order: `checkpoint_gate`, then `deleted_namespaces` or a ring `write_lock`, then
+/// `unhealthy_services` and `failure_reports`.
 pub struct ServiceDiscovery {
-    pub service_map: HashMap<String, Ring>,
-    pub unhealthy_services: HashMap<String, Ring>,
+    /// Rings by namespace, namespaces can be created and deleted at runtime.
+    /// Swapped as a whole, changes are serialized by `deleted_namespaces`.
+    pub service_map: ArcSwap<HashMap<String, Arc<Ring>>>,
+    /// Namespaces the registry started with, the ones from `horbo.yml`.
+    configured: HashSet<String>,
+    /// Namespaces of `horbo.yml` deleted at runtime, kept in snapshots so they don't
+    /// come back from the config on the next start.
+    deleted_namespaces: Mutex<HashSet<String>>,
+    pub unhealthy_services: RwLock<UnhealthySet>,
+    pub failure_reports: RwLock<FailureReports>,
+    /// Where registry changes are logged, state is memory only without one.
+    pub storage: Option<Arc<dyn Storage>>,
+    /// Changes logged so far, and how many of them are known to be on disk.
+    logged: AtomicU64,
+    synced: AtomicU64,
+    /// Shared by every change while it's applied and logged, taken exclusively by
+    /// `checkpoint` while it copies the registry so the copy matches the log.
+    checkpoint_gate: RwLock<()>,
+    /// Flipped once by `shut_down`, ends every watch stream.
+    shutting_down: watch::Sender<bool>,
 }
 
 impl ServiceDiscovery {
     pub fn new(service_map: HashMap<String, Ring>) -> Self {
         ServiceDiscovery {
-            service_map: service_map,
-            unhealthy_services: HashMap::new(),
+            configured: service_map.keys().cloned().collect(),
+            service_map: ArcSwap::from_pointee(
+                service_map
+                    .into_iter()
+                    .map(|(namespace, ring)| (namespace, Arc::new(ring)))
+                    .collect(),
+            ),
+            deleted_namespaces: Mutex::new(HashSet::new()),
+            unhealthy_services: RwLock::new(UnhealthySet::default()),
+            failure_reports: RwLock::new(FailureReports::default()),
+            storage: None,
+            logged: AtomicU64::new(0),
+            synced: AtomicU64::new(0),
+            checkpoint_gate: RwLock::new(()),
+            shutting_down: watch::Sender::new(false),
+        }
+    }
+
+    pub fn with_storage(mut self, storage: Arc<dyn Storage>) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    /// Ends every watch stream with `ErrorResponse::Unavailable` and refuses new
+    /// ones, so draining connections isn't held up by watchers that never leave.
+    /// Other calls are still served.
+    pub fn shut_down(&self) {
+        self.shutting_down.send_replace(true);
+    }
+
+    /// Logs a change already applied in memory, a no-op without storage. Callers
+    /// hold the locks the change was made under, so records land in order, and
+    /// `sync` once they let go of them.
+    fn persist(&self, record: Record) -> Result<(), ErrorResponse> {
+        match &self.storage {
+            Some(storage) => {
+                storage.append(&record)?;
+                self.logged.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Waits until every change logged so far is on disk. The fsync runs on the
+    /// blocking pool, and is shared by the changes logged before it starts.
+    async fn sync(&self) -> Result<(), ErrorResponse> {
+        let storage = match &self.storage {
+            Some(storage) => storage.clone(),
+            None => return Ok(()),
+        };
+
+        let logged = self.logged.load(Ordering::SeqCst);
+        if self.synced.load(Ordering::SeqCst) >= logged {
+            return Ok(());
+        }
+        tokio::task::spawn_blocking(move || storage.sync())
+            .await
+            .map_err(|e| ErrorResponse::Internal(e.to_string()))??;
+        self.synced.fetch_max(logged, Ordering::SeqCst);
+
+        Ok(())
+    }
+
+    /// Replays the stored snapshot and log on top of the namespaces from `horbo.yml`.
+    ///
+    /// Returns the number of records replayed.
+    ///
+    /// Notes:
+    /// - Namespaces listed in `horbo.yml` keep their configured settings, a recovered
+    ///   namespace of the same name only brings its nodes back.
+    /// - Namespaces listed in `horbo.yml` but deleted at runtime are deleted again.
+    /// - Records referring to namespaces or nodes that no longer exist are skipped, e.g.
+    ///   health changes of `horbo.yml` nodes which get a new service id on every start.
+    /// - Recovered nodes count as having just sent a heartbeat, so agents that didn't
+    ///   survive the restart are evicted once their namespace TTL runs out.
+    pub fn recover(&self) -> Result<usize, ErrorResponse> {
+        let storage = match &self.storage {
+            Some(storage) => storage.clone(),
+            None => return Ok(0),
+        };
+
+        let (snapshot, log) = storage.load()?;
+        let snapshot = snapshot.unwrap_or_default();
+        let mut records: Vec<Record> = snapshot
+            .deleted
+            .into_iter()
+            .map(|namespace| Record::NamespaceDeleted { namespace })
+            .collect();
+        for namespace in snapshot.namespaces {
+            records.push(Record::NamespaceCreated {
+                namespace: namespace.namespace.clone(),
+                definition: namespace.definition,
+            });
+            for node in namespace.nodes {
+                records.push(Record::NodeRegistered {
+                    namespace: namespace.namespace.clone(),
+                    node,
+                });
+            }
         }
+        records.extend(log);
+
+        let count = records.len();
+        for record in records {
+            match self.apply(record) {
+                Ok(_) | Err(ErrorResponse::BadRequest(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Applies a recovered record without logging it again.
+    fn apply(&self, record: Record) -> Result<(), ErrorResponse> {
+        let _gate = self.gate()?;
+
+        match record {
+            Record::NamespaceCreated {
+                namespace,
+                definition,
+            } => {
+                let mut deleted = self.namespaces()?;
+                if self.service_map.load().contains_key(&namespace) {
+                    return Ok(());
+                }
+                deleted.remove(&namespace);
+
+                let mut service_map = HashMap::clone(&self.service_map.load());
+                let ring = Arc::new(build(namespace.clone(), definition));
+                service_map.insert(namespace, ring);
+                self.service_map.store(Arc::new(service_map));
+            }
+            Record::NamespaceDeleted { namespace } => {
+                let mut deleted = self.namespaces()?;
+                if self.configured.contains(&namespace) {
+                    deleted.insert(namespace.clone());
+                }
+                let mut service_map = HashMap::clone(&self.service_map.load());
+                if let Some(ring) = service_map.remove(&namespace) {
+                    self.service_map.store(Arc::new(service_map));
+                    ring.close()?;
+                }
+                self.unhealthy_set()?.remove_namespace(&namespace);
+            }
+            Record::NodeRegistered { namespace, node } => {
+                let ring = match self.ring(&namespace)? {
+                    Some(ring) => ring,
+                    None => {
+                        return Err(ErrorResponse::BadRequest("namespace not found".to_string()))
+                    }
+                };
+                let _lock = Self::lock(&ring)?;
+                let service_id = ring.add_server(
+                    node.service_id,
+                    node.ip.clone(),
+                    node.port,
+                    node.metadata,
+                    node.owner,
+                )?;
+                if !node.healthy {
+                    ring.set_health_status(service_id.clone(), false)?;
+                    self.unhealthy_set()?
+                        .enter(&namespace, &service_id, &node.ip);
+                }
+            }
+            Record::NodeDeregistered {
+                namespace,
+                service_id,
+            } => {
+                if let Some(ring) = self.ring(&namespace)? {
+                    let _lock = Self::lock(&ring)?;
+                    ring.remove_server(service_id.clone())?;
+                }
+                self.unhealthy_set()?.exit(&namespace, &service_id);
+            }
+            Record::NodeHealth {
+                namespace,
+                service_id,
+                healthy,
+            } => {
+                let ring = match self.ring(&namespace)? {
+                    Some(ring) => ring,
+                    None => {
+                        return Err(ErrorResponse::BadRequest("namespace not found".to_string()))
+                    }
+                };
+                let _lock = Self::lock(&ring)?;
+                ring.set_health_status(service_id.clone(), healthy)?;
+                match ring.server(&service_id) {
+                    Some(server) if !healthy => {
+                        self.unhealthy_set()?
+                            .enter(&namespace, &service_id, &server.ip);
+                    }
+                    _ => {
+                        self.unhealthy_set()?.exit(&namespace, &service_id);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The ring of a namespace, if the namespace exists.
+    fn ring(&self, namespace: &str) -> Result<Option<Arc<Ring>>, ErrorResponse> {
+        Ok(self.service_map.load().get(namespace).cloned())
+    }
+
+    /// Every namespace ring as of now, namespaces created meanwhile aren't included.
+    fn rings(&self) -> Result<Vec<(String, Arc<Ring>)>, ErrorResponse> {
+        Ok(self
+            .service_map
+            .load()
+            .iter()
+            .map(|(namespace, ring)| (namespace.clone(), ring.clone()))
+            .collect())
+    }
+
+    /// Held while a change is applied and logged, see `checkpoint_gate`.
+    fn gate(&self) -> Result<RwLockReadGuard<'_, ()>, ErrorResponse> {
+        registry::acquire("checkpoint_gate", || self.checkpoint_gate.read())
+    }
+
+    /// Serializes namespace creation and deletion.
+    fn namespaces(&self) -> Result<MutexGuard<'_, HashSet<String>>, ErrorResponse> {
+        registry::acquire("namespaces", || self.deleted_namespaces.lock())
+    }
+
+    /// Serializes changes to the nodes of a single namespace.
+    fn lock(ring: &Ring) -> Result<MutexGuard<'_, ()>, ErrorResponse> {
+        registry::acquire("ring", || ring.write_lock.lock())
+    }
+
+    /// Fails unless `owner` registered the node, `None` stands for a caller trusted
+    /// with any node. Nodes of `horbo.yml` have no owner and accept anyone.
+    fn check_owner(
+        ring: &Ring,
+        service_id: &str,
+        owner: Option<&str>,
+    ) -> Result<(), ErrorResponse> {
+        let registered = ring.server(service_id).and_then(|server| server.owner);
+        match (owner, registered) {
+            (Some(owner), Some(registered)) if owner != registered => Err(
+                ErrorResponse::Forbidden("node was registered by another client".to_string()),
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    fn describe(ring: &Ring) -> NamespaceDescription {
+        NamespaceDescription {
+            namespace: ring.namespace.clone(),
+            settings: Some(ring.definition.repr()),
+            node_count: ring.server_count() as u32,
+            healthy_node_count: ring.healthy_server_count() as u32,
+        }
+    }
+
+    fn failure_reports(&self) -> Result<RwLockWriteGuard<'_, FailureReports>, ErrorResponse> {
+        registry::acquire("failure_reports", || self.failure_reports.write())
+    }
+
+    fn unhealthy_set(&self) -> Result<RwLockWriteGuard<'_, UnhealthySet>, ErrorResponse> {
+        registry::acquire("unhealthy_services", || self.unhealthy_services.write())
+    }
+
+    fn unhealthy_view(&self) -> Result<RwLockReadGuard<'_, UnhealthySet>, ErrorResponse> {
+        registry::acquire("unhealthy_services", || self.unhealthy_services.read())
+    }
+
+    /// Flips a node to unhealthy on its ring and records it in `unhealthy_services`,
+    /// `reason` ends up in the audit log. Callers hold the gate and the ring lock.
+    fn set_unhealthy(
+        &self,
+        namespace: &str,
+        ring: &Ring,
+        node: &str,
+        reason: &str,
+    ) -> Result<(), ErrorResponse> {
+        let server = match ring.resolve(node).and_then(|id| ring.server(&id)) {
+            Some(server) => server,
+            None => {
+                return Err(ErrorResponse::BadRequest(
+                    "can't find service inside the namespace".to_string(),
+                ))
+            }
+        };
+
+        ring.set_health_status(server.service_id.clone(), false)?;
+        if server.healthy {
+            tracing::info!(
+                target: AUDIT,
+                namespace,
+                service_id = %server.service_id,
+                ip = %server.ip,
+                reason,
+                "node marked unhealthy"
+            );
+        }
+        self.failure_reports()?.clear(namespace, &server.service_id);
+        self.unhealthy_set()?
+            .enter(namespace, &server.service_id, &server.ip);
+
+        self.persist(Record::NodeHealth {
+            namespace: namespace.to_string(),
+            service_id: server.service_id,
+            healthy: false,
+        })
     }
 }
 
@@ -25,14 +375,22 @@ impl ServiceDiscoveryUsecase for ServiceDiscovery {
     ///
     /// # Arguments
     /// - `namespace`: The logical group to which the node belongs (e.g., service name or environment).
-    /// - `ip_address`: The IP address of the node being registered.
+    /// - `ip_address`: The address the node advertises to clients.
+    /// - `port`: The port the node serves on, handed out to clients on lookup.
+    /// - `metadata`: Labels of the node, returned on lookup and usable as a selector.
+    /// - `owner`: Identity of the caller, the only one heartbeats and a deregistration
+    ///   of the node are accepted from afterwards.
     ///
     /// # Returns
-    /// - `Ok(unique_id)` where `unique_id` is the hashed ID derived from the node's IP address.
-    /// - `Err(ErrorResponse::BadRequest)` if the namespace doesn't exist in the service map.
+    /// - `Ok(unique_id)` where `unique_id` is the service id the agent must send in later calls.
+    /// - `Err(ErrorResponse::BadRequest)` if the namespace doesn't exist in the service map,
+    ///   or a metadata label has an empty key.
+    /// - `Err(ErrorResponse::Forbidden)` if another caller registered the same address.
     ///
     /// # Behavior
-    /// - Returns a unique hash for the given IP address.
+    /// - Generates a new ULID service id, unless the same address and port are already
+    ///   registered in which case the existing id is returned and its metadata is
+    ///   replaced, watchers get a `MetadataChanged` event when the labels differ.
     /// - Looks up the corresponding consistent hash ring for the namespace.
     /// - Adds the node to the ring if the namespace exists.
     /// - Returns an error if the namespace is unknown.
@@ -40,21 +398,103 @@ impl ServiceDiscoveryUsecase for ServiceDiscovery {
         &self,
         namespace: String,
         ip_address: String,
+        port: u32,
+        metadata: HashMap<String, String>,
+        owner: String,
     ) -> Result<AgentRegistrationResponse, ErrorResponse> {
-        let ring = self.service_map.get(&namespace);
+        if metadata.keys().any(|key| key.trim().is_empty()) {
+            return Err(ErrorResponse::BadRequest(
+                "metadata keys can't be empty".to_string(),
+            ));
+        }
 
-        match ring {
-            Some(ring) => {
-                let unique_id = ring.add_server(ip_address);
-                match unique_id {
-                    Ok(id) => return Ok(AgentRegistrationResponse{
-                        service_id: id.to_string(),
-                    }),
-                    Err(e) => return Err(e),
-                }
+        let response = {
+            let _gate = self.gate()?;
+            let ring = match self.ring(&namespace)? {
+                Some(ring) => ring,
+                None => return Err(ErrorResponse::BadRequest("namespace not found".to_string())),
+            };
+
+            let _lock = Self::lock(&ring)?;
+            /* Deleted while waiting for the lock, a change would be lost with the ring */
+            if ring.closed() {
+                return Err(ErrorResponse::BadRequest("namespace not found".to_string()));
             }
-            None => return Err(ErrorResponse::BadRequest("namespace not found".to_string())),
+            let id = ring.add_server(
+                Ulid::new().to_string(),
+                ip_address,
+                port,
+                metadata,
+                Some(owner),
+            )?;
+            if let Some(server) = ring.server(&id) {
+                self.persist(Record::NodeRegistered {
+                    namespace,
+                    node: NodeRecord {
+                        service_id: id.clone(),
+                        ip: server.ip,
+                        port: server.port,
+                        healthy: server.healthy,
+                        metadata: server.metadata,
+                        owner: server.owner,
+                    },
+                })?;
+            }
+            AgentRegistrationResponse { service_id: id }
         };
+
+        self.sync().await?;
+        Ok(response)
+    }
+
+    /// Removes a node (server) and all of its virtual nodes from the namespace ring.
+    ///
+    /// # Arguments
+    /// - `namespace`: The logical group the node was registered under.
+    /// - `service_id`: The id returned by `register_node`.
+    /// - `owner`: Identity of the caller, `None` for admins who may remove any node.
+    ///
+    /// # Returns
+    /// - `Ok(())` once the node left the ring.
+    /// - `Err(ErrorResponse::BadRequest)` if the namespace doesn't exist or no such node
+    ///   is registered.
+    /// - `Err(ErrorResponse::Forbidden)` if another caller registered the node.
+    ///
+    /// # Behavior
+    /// - The node is also dropped from `unhealthy_services` so it's no longer gossiped
+    ///   to other agents in heartbeat responses.
+    async fn deregister_node(
+        &self,
+        namespace: String,
+        service_id: String,
+        owner: Option<String>,
+    ) -> Result<(), ErrorResponse> {
+        {
+            let _gate = self.gate()?;
+            let ring = match self.ring(&namespace)? {
+                Some(ring) => ring,
+                None => return Err(ErrorResponse::BadRequest("namespace not found".to_string())),
+            };
+
+            let _lock = Self::lock(&ring)?;
+            /* Deleted while waiting for the lock, the node went with it */
+            if ring.closed() {
+                return Err(ErrorResponse::BadRequest("namespace not found".to_string()));
+            }
+            Self::check_owner(&ring, &service_id, owner.as_deref())?;
+            ring.remove_server(service_id.clone())?;
+
+            /* Node might never have been unhealthy, nothing to clean up then */
+            self.unhealthy_set()?.exit(&namespace, &service_id);
+            self.failure_reports()?.clear(&namespace, &service_id);
+
+            self.persist(Record::NodeDeregistered {
+                namespace,
+                service_id,
+            })?;
+        }
+
+        self.sync().await
     }
 
     /// Looks up a service instance for the given client IP using consistent hashing.
@@ -62,152 +502,707 @@ impl ServiceDiscoveryUsecase for ServiceDiscovery {
     /// # Arguments
     /// - `namespace`: The logical group of services to look up from.
     /// - `client_ip_address`: The IP address of the client requesting a service.
+    /// - `selector`: Metadata labels the node must carry, any node when empty.
     ///
     /// # Returns
-    /// - `Ok(service_ip)`: The selected service IP address from the consistent hash ring.
+    /// - `Ok(LookupResponse)`: Host, port and service id of the node selected from the consistent hash ring.
     /// - `Err(ErrorResponse::BadRequest)`: If the namespace doesn't exist.
     /// - `Err(ErrorResponse::Internal)`: If the ring lookup fails due to an internal error.
     ///
     /// # Behavior
     /// - Retrieves the consistent hash ring associated with the given namespace.
-    /// - Uses the client IP as a key to find the appropriate service IP from the ring.
+    /// - Uses the client IP as a key to find the first healthy node clockwise on the ring,
+    ///   wrapping around to the start when the client hash is past the last node.
+    /// - With a selector, nodes not carrying every label are skipped, so the hash is
+    ///   computed over the matching nodes only.
     /// - Handles and forwards any errors that occur during lookup.
     async fn service_lookup(
         &self,
         namespace: String,
         client_ip_address: String,
+        selector: HashMap<String, String>,
     ) -> Result<LookupResponse, ErrorResponse> {
-        let ring = self.service_map.get(&namespace);
+        let ring = self.ring(&namespace)?;
 
         match ring {
-            Some(ring) => match ring.get(client_ip_address) {
-                Ok(service_ip) => {
-                    return Ok(LookupResponse { ip_address: service_ip, namespace: ring.namespace.clone() });
-                }
-                Err(e) => return Err(e),
+            Some(ring) => match ring.get(client_ip_address, &selector) {
+                Ok(node) => Ok(LookupResponse {
+                    ip_address: node.ip,
+                    namespace: ring.namespace.clone(),
+                    port: node.port,
+                    service_id: node.service_id,
+                    metadata: node.metadata,
+                }),
+                Err(e) => Err(e),
             },
-            None => return Err(ErrorResponse::BadRequest("namespace not found".to_string())),
+            None => Err(ErrorResponse::BadRequest("namespace not found".to_string())),
         }
     }
 
     /// Handles heartbeat from a node in the specified namespace.
     ///
-    /// Based on the node's reported CPU and memory usage, it determines whether the node
-    /// is healthy or not, then updates its health status in the service ring accordingly.
+    /// Based on the node's reported CPU and memory usage, the namespace `HealthPolicy`
+    /// determines whether the node is healthy or not, then its health status in the
+    /// service ring is updated accordingly.
     ///
-    /// A node is considered healthy if:
+    /// With the default `ThresholdPolicy` a node is considered healthy if:
     /// - `cpu_usage` < 80.00
     /// - `memory_usage` < 85.00
     ///
+    /// A node whose health changed enters or leaves `unhealthy_services` accordingly.
+    ///
     /// After updating the node's status, the function compiles a list of all unhealthy nodes
     /// across all namespaces and returns it.
     ///
     /// Arguments:
     /// - `namespace`: The namespace the node belongs to.
-    /// - `ip_address`: IP address of the node sending the heartbeat.
+    /// - `service_id`: Id the node got back from `register_node`.
     /// - `metric`: Current CPU and memory utilization of the node.
+    /// - `owner`: Identity of the caller, `None` for UDP frames carrying the shared key.
     ///
     /// Returns:
-    /// - `Ok(HashMap<String, Vec<Node>>)` containing all unhealthy nodes grouped by namespace.
+    /// - `Ok(HeartbeatResponse)` containing all unhealthy nodes grouped by namespace.
+    /// - `Err(ErrorResponse::BadRequest)` if a usage isn't a percentage between 0 and 100.
+    /// - `Err(ErrorResponse::Forbidden)` if another caller registered the node.
     /// - `Err(ErrorResponse)` if updating the node’s health status fails.
     ///
     /// Notes:
     /// - If the namespace doesn't exist in `service_map`, health status is not updated, but the function proceeds.
     /// - All unhealthy nodes from all namespaces are included in the response regardless of which node sent the heartbeat.
-
     async fn node_heartbeat(
         &self,
         namespace: String,
-        ip_address: String,
+        service_id: String,
         metric: UtilizationMetric,
+        owner: Option<String>,
     ) -> Result<HeartbeatResponse, ErrorResponse> {
-        let mut is_healthy = false;
-        if metric.cpu_usage < 80.00 && metric.memory_usage < 85.00 {
-            is_healthy = true
-        }
-
-        let ring = self.service_map.get(&namespace);
+        metric.validate()?;
+        if let Some(ring) = self.ring(&namespace)? {
+            let _gate = self.gate()?;
+            let _lock = Self::lock(&ring)?;
+            /* Deleted while waiting for the lock, there's no node left to update */
+            if !ring.closed() {
+                Self::check_owner(&ring, &service_id, owner.as_deref())?;
+                ring.record_heartbeat(&service_id)?;
+                let (was_healthy, is_healthy) = ring.apply_metric(&service_id, &metric)?;
 
-        match ring {
-            Some(ring) => match ring.set_health_status(ip_address, is_healthy) {
-                Ok(_) => {}
-                Err(e) => return Err(e),
-            },
-            None => {}
-        }
-
-        /* Build unhealthy nodes response */
-        let mut heartbeat_response = HeartbeatResponse{
-            unhealthy_services: Vec::new(),
-        };
-
-
-        let mut unhealthy_nodes: HashMap<String, usize> = HashMap::new();
-        for (namespace, nodes) in self.unhealthy_services.iter() {
-            match unhealthy_nodes.get(namespace) {
-                Some(_) => {
-                    /* Do nothing, as we don't expect duplicate keys on unhealthy_services */
-                },
-                None => {
-                    let node_map = NodeMap{
-                        namespace:namespace.clone(),
-                        node: nodes.repr(),
-                    };
+                if !is_healthy {
+                    if let Some(server) = ring.server(&service_id) {
+                        self.unhealthy_set()?
+                            .enter(&namespace, &service_id, &server.ip);
+                    }
+                } else {
+                    /* Also covers nodes marked by a failure report or a timeout */
+                    self.unhealthy_set()?.exit(&namespace, &service_id);
+                }
 
-                    heartbeat_response.unhealthy_services.push(node_map);
-                    unhealthy_nodes.insert(namespace.clone(), heartbeat_response.unhealthy_services.len() - 1);
+                /* Only transitions are logged, a heartbeat alone doesn't change the registry */
+                if was_healthy != is_healthy {
+                    tracing::info!(
+                        target: AUDIT,
+                        namespace,
+                        service_id,
+                        cpu_usage = metric.cpu_usage,
+                        memory_usage = metric.memory_usage,
+                        reason = "heartbeat utilization",
+                        "node marked {}",
+                        if is_healthy { "healthy" } else { "unhealthy" }
+                    );
+                    self.persist(Record::NodeHealth {
+                        namespace: namespace.clone(),
+                        service_id,
+                        healthy: is_healthy,
+                    })?;
                 }
             }
         }
+        self.sync().await?;
 
+        /* Build unhealthy nodes response */
+        let heartbeat_response = HeartbeatResponse {
+            unhealthy_services: self.unhealthy_view()?.repr(),
+        };
 
         Ok(heartbeat_response)
     }
 
-    /// Marks a node as unhealthy in the specified namespace.
+    /// Records a failure report and marks the node as unhealthy once the namespace quorum is met.
     ///
     /// This function updates the node status inside the service ring and
-    /// registers it in the unhealthy ring for the given namespace.
+    /// registers it in the unhealthy set for the given namespace.
     ///
     /// Behavior:
-    /// - Attempts to set the node’s health status to `false` in the healthy service ring (`service_map`).
-    /// - Then adds the node to the unhealthy ring (`unhealthy_services`) for tracking.
+    /// - Counts distinct reporters of the node within the namespace `failure_quorum` window,
+    ///   a reporter repeating itself only refreshes its report.
+    /// - Once enough reporters agree, sets the node’s health status to `false` in the healthy
+    ///   service ring (`service_map`) and drops the pending reports.
+    /// - Then adds the node to `unhealthy_services` for tracking, until a heartbeat shows it recovered.
     ///
     /// Arguments:
     /// - `namespace`: The namespace to which the node belongs.
-    /// - `ip_address`: The IP address of the node to be marked unhealthy.
+    /// - `node`: Service id of the node to be marked unhealthy, its advertised address
+    ///   is accepted as well for reporters that only know where they connected to.
+    /// - `reporter`: Identity of the caller filing the report, its certificate name or
+    ///   API key with auth and its host without.
     ///
     /// Returns:
-    /// - `Ok(())` if the node was successfully marked as unhealthy or if the namespace doesn't exist.
-    /// - `Err(ErrorResponse)` if updating health status or adding to the unhealthy ring fails.
+    /// - `Ok(())` if the report was recorded, whether or not it tipped the quorum, or if the namespace doesn't exist.
+    /// - `Err(ErrorResponse)` if updating health status or adding to the unhealthy set fails.
     ///
     /// Notes:
-    /// - If the namespace is not found in either `service_map` or `unhealthy_services`, the function exits silently.
+    /// - If the namespace is not found in `service_map`, the function exits silently.
     async fn mark_node_unhealthy(
         &self,
         namespace: String,
-        ip_address: String,
+        node: String,
+        reporter: String,
     ) -> Result<(), ErrorResponse> {
-        let ring = self.service_map.get(&namespace);
+        {
+            let _gate = self.gate()?;
+            let ring = match self.ring(&namespace)? {
+                Some(ring) => ring,
+                None => return Ok(()),
+            };
+            let _lock = Self::lock(&ring)?;
+            /* Deleted while waiting for the lock, nothing left to report on */
+            if ring.closed() {
+                return Ok(());
+            }
 
-        match ring {
-            Some(ring) => match ring.set_health_status(ip_address.clone(), false) {
-                Ok(_) => {}
-                Err(e) => return Err(e),
-            },
-            None => return Ok(()),
+            let service_id = match ring.resolve(&node) {
+                Some(id) => id,
+                None => {
+                    return Err(ErrorResponse::BadRequest(
+                        "can't find service inside the namespace".to_string(),
+                    ))
+                }
+            };
+
+            let quorum = &ring.settings.failure_quorum;
+            let reporters =
+                self.failure_reports()?
+                    .record(&namespace, &service_id, &reporter, quorum.window);
+
+            let required = quorum.required(ring.server_count());
+            tracing::info!(
+                target: AUDIT,
+                namespace,
+                service_id,
+                reporter,
+                reporters,
+                required,
+                "failure reported"
+            );
+            if reporters < required {
+                return Ok(());
+            }
+
+            self.set_unhealthy(&namespace, &ring, &service_id, "failure reports")?;
         }
 
-        let unhealthy_ring = self.unhealthy_services.get(&namespace);
+        self.sync().await
+    }
 
-        match unhealthy_ring {
-            Some(ring) => match ring.add_server(ip_address) {
-                Ok(_) => {}
-                Err(e) => return Err(e),
-            },
-            None => {}
+    /// Applies the heartbeat timeouts of every namespace.
+    ///
+    /// Behavior:
+    /// - Nodes silent for longer than the namespace `heartbeat_timeout` are marked unhealthy
+    ///   and enter `unhealthy_services`.
+    /// - Nodes silent for longer than the namespace `eviction_ttl` are removed from the ring
+    ///   and from `unhealthy_services`.
+    /// - Failure reports older than the namespace quorum window are dropped.
+    ///
+    /// Returns:
+    /// - `Ok(())` once every namespace has been checked.
+    /// - `Err(ErrorResponse)` if updating a ring fails.
+    ///
+    /// Notes:
+    /// - Nodes that never sent a heartbeat (e.g. listed in `horbo.yml`) are left alone.
+    async fn reap_silent_nodes(&self) -> Result<(), ErrorResponse> {
+        {
+            let _gate = self.gate()?;
+            for (namespace, ring) in self.rings()? {
+                let _lock = Self::lock(&ring)?;
+                /* Deleted since the rings were listed */
+                if ring.closed() {
+                    continue;
+                }
+                for (service_id, _) in ring.silent_servers(ring.settings.eviction_ttl) {
+                    ring.remove_server(service_id.clone())?;
+                    tracing::info!(
+                        target: AUDIT,
+                        namespace,
+                        service_id,
+                        "node evicted after going silent"
+                    );
+                    self.unhealthy_set()?.exit(&namespace, &service_id);
+                    self.failure_reports()?.clear(&namespace, &service_id);
+                    self.persist(Record::NodeDeregistered {
+                        namespace: namespace.clone(),
+                        service_id,
+                    })?;
+                }
+
+                self.failure_reports()?
+                    .expire(&namespace, ring.settings.failure_quorum.window);
+
+                for (service_id, healthy) in ring.silent_servers(ring.settings.heartbeat_timeout) {
+                    /* Already flipped on the ring doesn't mean it's tracked in the set */
+                    if healthy || !self.unhealthy_set()?.contains(&namespace, &service_id) {
+                        self.set_unhealthy(&namespace, &ring, &service_id, "heartbeat timeout")?;
+                    }
+                }
+            }
         }
 
-        Ok(())
+        self.sync().await
+    }
+
+    /// Creates a namespace at runtime so agents can register into it right away.
+    ///
+    /// # Arguments
+    /// - `namespace`: Name of the new namespace.
+    /// - `definition`: Ring settings of the namespace, same as a `services:` entry of `horbo.yml`.
+    ///
+    /// # Returns
+    /// - `Ok(NamespaceDescription)` describing the namespace as created.
+    /// - `Err(ErrorResponse::BadRequest)` if the name is empty or the namespace already exists.
+    ///
+    /// # Notes
+    /// - Namespaces created this way are not written back to `horbo.yml`.
+    async fn create_namespace(
+        &self,
+        namespace: String,
+        definition: NamespaceDefinition,
+    ) -> Result<NamespaceDescription, ErrorResponse> {
+        if namespace.trim().is_empty() {
+            return Err(ErrorResponse::BadRequest(
+                "namespace name can't be empty".to_string(),
+            ));
+        }
+
+        /* Same rules as a `services:` entry of `horbo.yml` */
+        let mut validator = Validator::new("", &[]);
+        definition.validate("settings", &mut validator);
+        let problems = validator.into_problems();
+        if !problems.is_empty() {
+            let problems: Vec<String> = problems.iter().map(|p| p.to_string()).collect();
+            return Err(ErrorResponse::BadRequest(problems.join(", ")));
+        }
+
+        let ring = {
+            let _gate = self.gate()?;
+            let mut deleted = self.namespaces()?;
+            let mut service_map = HashMap::clone(&self.service_map.load());
+
+            if service_map.contains_key(&namespace) {
+                return Err(ErrorResponse::BadRequest(
+                    "namespace already exists".to_string(),
+                ));
+            }
+            deleted.remove(&namespace);
+
+            let ring = Arc::new(build(namespace.clone(), definition.clone()));
+            service_map.insert(namespace.clone(), ring.clone());
+            self.service_map.store(Arc::new(service_map));
+
+            self.persist(Record::NamespaceCreated {
+                namespace,
+                definition,
+            })?;
+
+            ring
+        };
+        self.sync().await?;
+
+        Ok(Self::describe(&ring))
+    }
+
+    /// Lists every namespace with its settings and node counts, sorted by name.
+    async fn list_namespaces(&self) -> Result<ListNamespacesResponse, ErrorResponse> {
+        let mut namespaces: Vec<NamespaceDescription> = self
+            .rings()?
+            .iter()
+            .map(|(_, ring)| Self::describe(ring))
+            .collect();
+        namespaces.sort_by(|a, b| a.namespace.cmp(&b.namespace));
+
+        Ok(ListNamespacesResponse { namespaces })
+    }
+
+    /// Describes a single namespace.
+    ///
+    /// # Returns
+    /// - `Ok(NamespaceDescription)` with the effective settings and node counts.
+    /// - `Err(ErrorResponse::BadRequest)` if the namespace doesn't exist.
+    async fn describe_namespace(
+        &self,
+        namespace: String,
+    ) -> Result<NamespaceDescription, ErrorResponse> {
+        match self.ring(&namespace)? {
+            Some(ring) => Ok(Self::describe(&ring)),
+            None => Err(ErrorResponse::BadRequest("namespace not found".to_string())),
+        }
+    }
+
+    /// Deletes a namespace along with every node registered into it.
+    ///
+    /// # Returns
+    /// - `Ok(())` once the namespace is gone.
+    /// - `Err(ErrorResponse::BadRequest)` if the namespace doesn't exist.
+    ///
+    /// # Behavior
+    /// - Nodes of the namespace leave `unhealthy_services` and their pending failure
+    ///   reports are dropped, agents still heartbeating into it get `namespace not found`
+    ///   on their next registration.
+    /// - A namespace of `horbo.yml` stays deleted across restarts, until created again.
+    async fn delete_namespace(&self, namespace: String) -> Result<(), ErrorResponse> {
+        {
+            let _gate = self.gate()?;
+            let mut deleted = self.namespaces()?;
+            let mut service_map = HashMap::clone(&self.service_map.load());
+
+            let ring = match service_map.remove(&namespace) {
+                Some(ring) => ring,
+                None => return Err(ErrorResponse::BadRequest("namespace not found".to_string())),
+            };
+
+            /* Let changes already in flight on the ring finish first */
+            let _lock = Self::lock(&ring)?;
+            self.service_map.store(Arc::new(service_map));
+            ring.close()?;
+
+            self.unhealthy_set()?.remove_namespace(&namespace);
+            self.failure_reports()?.remove_namespace(&namespace);
+            if self.configured.contains(&namespace) {
+                deleted.insert(namespace.clone());
+            }
+
+            self.persist(Record::NamespaceDeleted { namespace })?;
+        }
+
+        self.sync().await
+    }
+
+    /// Writes a snapshot of every namespace and its registered nodes, which lets the
+    /// storage drop the log it covers.
+    ///
+    /// Returns:
+    /// - `Ok(())` once the snapshot is stored, or right away without storage.
+    /// - `Err(ErrorResponse)` if the storage fails to write it.
+    ///
+    /// Notes:
+    /// - Nodes listed in `horbo.yml` are not part of it, they come back from the config.
+    /// - Namespaces of `horbo.yml` deleted at runtime are listed so they stay deleted.
+    async fn checkpoint(&self) -> Result<(), ErrorResponse> {
+        let storage = match &self.storage {
+            Some(storage) => storage,
+            None => return Ok(()),
+        };
+
+        /* Nothing is applied while the state is copied, so it matches the sequence
+        number exactly. Writing it out doesn't need the gate, changes made meanwhile
+        are logged after it */
+        let snapshot = {
+            let _gate = registry::acquire("checkpoint_gate", || self.checkpoint_gate.write())?;
+
+            let mut snapshot = Snapshot {
+                seq: storage.last_seq()?,
+                deleted: self.namespaces()?.iter().cloned().collect(),
+                ..Snapshot::default()
+            };
+            snapshot.deleted.sort();
+            for (namespace, ring) in self.rings()? {
+                snapshot.namespaces.push(NamespaceRecord {
+                    namespace,
+                    definition: ring.definition.clone(),
+                    nodes: ring
+                        .servers()
+                        .into_iter()
+                        .filter(|server| ring.last_heartbeat(&server.service_id).is_some())
+                        .map(|server| NodeRecord {
+                            service_id: server.service_id,
+                            ip: server.ip,
+                            port: server.port,
+                            healthy: server.healthy,
+                            metadata: server.metadata,
+                            owner: server.owner,
+                        })
+                        .collect(),
+                });
+            }
+            snapshot
+        };
+
+        let storage = storage.clone();
+        tokio::task::spawn_blocking(move || storage.snapshot(&snapshot))
+            .await
+            .map_err(|e| ErrorResponse::Internal(e.to_string()))?
+    }
+
+    /// Every node currently considered unhealthy, grouped by namespace, same as the
+    /// list gossiped in heartbeat responses.
+    async fn unhealthy_services(&self) -> Result<Vec<NodeMap>, ErrorResponse> {
+        Ok(self.unhealthy_view()?.repr())
+    }
+
+    /// Lists the nodes of a namespace with their latest heartbeat.
+    ///
+    /// # Arguments
+    /// - `namespace`: The namespace to list.
+    /// - `healthy`: Only nodes with this health status, any when `None`.
+    /// - `labels`: Only nodes carrying every one of these metadata labels.
+    ///
+    /// # Returns
+    /// - `Ok(ListInstancesResponse)` with the matching nodes in ring order.
+    /// - `Err(ErrorResponse::BadRequest)` if the namespace doesn't exist.
+    async fn list_instances(
+        &self,
+        namespace: String,
+        healthy: Option<bool>,
+        labels: HashMap<String, String>,
+    ) -> Result<ListInstancesResponse, ErrorResponse> {
+        let ring = match self.ring(&namespace)? {
+            Some(ring) => ring,
+            None => return Err(ErrorResponse::BadRequest("namespace not found".to_string())),
+        };
+
+        let instances = ring
+            .instances()
+            .into_iter()
+            .filter(|(node, _)| healthy.is_none_or(|healthy| node.healthy == healthy))
+            .filter(|(node, _)| node.matches(&labels))
+            .map(|(node, heartbeat)| {
+                let mut instance = node.repr();
+                if let Some(heartbeat) = heartbeat {
+                    instance.last_heartbeat_unix_ms = heartbeat.unix_ms();
+                    if let Some(metric) = heartbeat.metric {
+                        instance.cpu_usage = metric.cpu_usage;
+                        instance.memory_usage = metric.memory_usage;
+                    }
+                }
+                instance
+            })
+            .collect();
+
+        Ok(ListInstancesResponse {
+            namespace,
+            instances,
+        })
+    }
+
+    /// Streams the membership of a namespace, see `watcher::run`.
+    ///
+    /// # Returns
+    /// - `Ok(Receiver)` yielding a snapshot of every node first, then one event per
+    ///   node added, removed or changing health, in revision order.
+    /// - `Err(ErrorResponse::BadRequest)` if the namespace doesn't exist.
+    /// - `Err(ErrorResponse::Unavailable)` once the server is shutting down.
+    ///
+    /// # Notes
+    /// - Dropping the receiver stops the stream.
+    /// - The stream ends with a `NamespaceDeleted` event when the namespace is deleted,
+    ///   or with `ErrorResponse::Unavailable` on `shut_down`.
+    async fn watch(
+        &self,
+        namespace: String,
+    ) -> Result<mpsc::Receiver<Result<MembershipEvent, ErrorResponse>>, ErrorResponse> {
+        if *self.shutting_down.borrow() {
+            return Err(ErrorResponse::Unavailable(
+                watcher::SHUTTING_DOWN.to_string(),
+            ));
+        }
+        let ring = match self.ring(&namespace)? {
+            Some(ring) => ring,
+            None => return Err(ErrorResponse::BadRequest("namespace not found".to_string())),
+        };
+
+        let (tx, rx) = mpsc::channel(watcher::WATCHER_BUFFER);
+        tokio::spawn(watcher::run(ring, tx, self.shutting_down.subscribe()));
+
+        Ok(rx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::file::FileStorage;
+    use std::path::Path;
+
+    /// A registry started from a `horbo.yml` declaring `payment`, recovered from `dir`.
+    fn started(dir: &Path) -> ServiceDiscovery {
+        let mut services = HashMap::new();
+        services.insert(
+            "payment".to_string(),
+            build("payment".to_string(), NamespaceDefinition::default()),
+        );
+
+        let discovery =
+            ServiceDiscovery::new(services).with_storage(Arc::new(FileStorage::open(dir).unwrap()));
+        discovery.recover().unwrap();
+        discovery
+    }
+
+    fn service_ids(discovery: &ServiceDiscovery, namespace: &str) -> Vec<String> {
+        let mut ids: Vec<String> = discovery
+            .ring(namespace)
+            .unwrap()
+            .unwrap()
+            .servers()
+            .into_iter()
+            .map(|server| server.service_id)
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    async fn register(discovery: &ServiceDiscovery, namespace: &str, host: &str) -> String {
+        discovery
+            .register_node(
+                namespace.to_string(),
+                host.to_string(),
+                5000,
+                HashMap::new(),
+                host.to_string(),
+            )
+            .await
+            .unwrap()
+            .service_id
+    }
+
+    #[tokio::test]
+    async fn recovers_from_the_log_and_the_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let discovery = started(dir.path());
+        discovery
+            .create_namespace("booking".to_string(), NamespaceDefinition::default())
+            .await
+            .unwrap();
+        let a = register(&discovery, "payment", "10.0.0.1").await;
+        let b = register(&discovery, "booking", "10.0.0.2").await;
+        discovery.checkpoint().await.unwrap();
+        let c = register(&discovery, "payment", "10.0.0.3").await;
+        discovery
+            .deregister_node("booking".to_string(), b, Some("10.0.0.2".to_string()))
+            .await
+            .unwrap();
+
+        let recovered = started(dir.path());
+        let mut expected = vec![a, c];
+        expected.sort();
+        assert_eq!(service_ids(&recovered, "payment"), expected);
+        assert!(service_ids(&recovered, "booking").is_empty());
+    }
+
+    #[tokio::test]
+    async fn recovers_health_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let discovery = started(dir.path());
+        let a = register(&discovery, "payment", "10.0.0.1").await;
+        discovery
+            .mark_node_unhealthy("payment".to_string(), a.clone(), "10.1.0.1".to_string())
+            .await
+            .unwrap();
+
+        let recovered = started(dir.path());
+        let ring = recovered.ring("payment").unwrap().unwrap();
+        assert!(!ring.server(&a).unwrap().healthy);
+        assert!(recovered.unhealthy_set().unwrap().contains("payment", &a));
+    }
+
+    #[tokio::test]
+    async fn deleted_config_namespaces_stay_deleted() {
+        let dir = tempfile::tempdir().unwrap();
+        let discovery = started(dir.path());
+        discovery
+            .delete_namespace("payment".to_string())
+            .await
+            .unwrap();
+
+        /* Once from the log, then from a snapshot */
+        let recovered = started(dir.path());
+        assert!(recovered.ring("payment").unwrap().is_none());
+        recovered.checkpoint().await.unwrap();
+        let recovered = started(dir.path());
+        assert!(recovered.ring("payment").unwrap().is_none());
+
+        recovered
+            .create_namespace("payment".to_string(), NamespaceDefinition::default())
+            .await
+            .unwrap();
+        recovered.checkpoint().await.unwrap();
+        assert!(started(dir.path()).ring("payment").unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn only_the_registrant_heartbeats_or_deregisters_a_node() {
+        let dir = tempfile::tempdir().unwrap();
+        let discovery = started(dir.path());
+        let a = register(&discovery, "payment", "10.0.0.1").await;
+        let metric = || UtilizationMetric {
+            cpu_usage: 10.0,
+            memory_usage: 10.0,
+        };
+
+        let other = Some("10.0.0.9".to_string());
+        assert!(matches!(
+            discovery
+                .node_heartbeat("payment".to_string(), a.clone(), metric(), other.clone())
+                .await,
+            Err(ErrorResponse::Forbidden(_))
+        ));
+        assert!(matches!(
+            discovery
+                .deregister_node("payment".to_string(), a.clone(), other)
+                .await,
+            Err(ErrorResponse::Forbidden(_))
+        ));
+
+        /* A restart keeps the owner, admins and the owner itself get through */
+        let recovered = started(dir.path());
+        recovered
+            .node_heartbeat(
+                "payment".to_string(),
+                a.clone(),
+                metric(),
+                Some("10.0.0.1".to_string()),
+            )
+            .await
+            .unwrap();
+        recovered
+            .deregister_node("payment".to_string(), a, None)
+            .await
+            .unwrap();
+        assert!(service_ids(&recovered, "payment").is_empty());
+    }
+
+    #[tokio::test]
+    async fn rejects_usages_that_are_not_percentages() {
+        let dir = tempfile::tempdir().unwrap();
+        let discovery = started(dir.path());
+        let a = register(&discovery, "payment", "10.0.0.1").await;
+
+        for (cpu_usage, memory_usage) in [(f32::NAN, 10.0), (-1.0, 10.0), (10.0, 100.5)] {
+            let metric = UtilizationMetric {
+                cpu_usage,
+                memory_usage,
+            };
+            assert!(matches!(
+                discovery
+                    .node_heartbeat("payment".to_string(), a.clone(), metric, None)
+                    .await,
+                Err(ErrorResponse::BadRequest(_))
+            ));
+        }
+        assert!(
+            discovery
+                .ring("payment")
+                .unwrap()
+                .unwrap()
+                .server(&a)
+                .unwrap()
+                .healthy
+        );
     }
 }
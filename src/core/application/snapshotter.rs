@@ -0,0 +1,24 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::core::{
+    application::service_discovery::ServiceDiscovery, domain::server::ServiceDiscoveryUsecase,
+};
+
+/// How often registry state is snapshotted when `horbo.yml` doesn't say.
+pub const DEFAULT_SNAPSHOT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Background compaction, snapshots the registry so the write-ahead log
+/// doesn't grow without bound. Runs until the runtime stops.
+pub async fn run(service: Arc<ServiceDiscovery>, every: Duration) {
+    let mut interval = tokio::time::interval(every);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+
+        if let Err(e) = service.checkpoint().await {
+            tracing::error!(error = %e, "failed to snapshot registry");
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use crate::core::domain::{
+    data::UtilizationMetric,
+    health::{HealthPolicy, NodeHealth},
+};
+
+pub const DEFAULT_CPU_LIMIT: f32 = 80.00;
+pub const DEFAULT_MEMORY_LIMIT: f32 = 85.00;
+
+/// Static CPU/memory limits with a lower recovery watermark.
+///
+/// A node becomes unhealthy as soon as it reaches either limit, and only
+/// returns to healthy after `recover_after_beats` consecutive heartbeats
+/// below both recovery watermarks.
+#[derive(Debug, Clone)]
+pub struct ThresholdPolicy {
+    pub cpu_limit: f32,
+    pub memory_limit: f32,
+    pub cpu_recover_below: f32,
+    pub memory_recover_below: f32,
+    pub recover_after_beats: u32,
+}
+
+impl Default for ThresholdPolicy {
+    fn default() -> Self {
+        ThresholdPolicy {
+            cpu_limit: DEFAULT_CPU_LIMIT,
+            memory_limit: DEFAULT_MEMORY_LIMIT,
+            cpu_recover_below: DEFAULT_CPU_LIMIT,
+            memory_recover_below: DEFAULT_MEMORY_LIMIT,
+            recover_after_beats: 1,
+        }
+    }
+}
+
+impl HealthPolicy for ThresholdPolicy {
+    fn evaluate(&self, current: NodeHealth, metric: &UtilizationMetric) -> NodeHealth {
+        if metric.cpu_usage >= self.cpu_limit || metric.memory_usage >= self.memory_limit {
+            return NodeHealth {
+                healthy: false,
+                recovering_beats: 0,
+            };
+        }
+
+        if current.healthy {
+            return current;
+        }
+
+        if metric.cpu_usage < self.cpu_recover_below
+            && metric.memory_usage < self.memory_recover_below
+        {
+            let recovering_beats = current.recovering_beats + 1;
+            if recovering_beats >= self.recover_after_beats {
+                return NodeHealth {
+                    healthy: true,
+                    recovering_beats: 0,
+                };
+            }
+
+            return NodeHealth {
+                healthy: false,
+                recovering_beats,
+            };
+        }
+
+        /* Between the watermarks, recovery has to start over */
+        NodeHealth {
+            healthy: false,
+            recovering_beats: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> ThresholdPolicy {
+        ThresholdPolicy {
+            cpu_limit: 80.0,
+            memory_limit: 85.0,
+            cpu_recover_below: 60.0,
+            memory_recover_below: 70.0,
+            recover_after_beats: 2,
+        }
+    }
+
+    fn metric(cpu_usage: f32, memory_usage: f32) -> UtilizationMetric {
+        UtilizationMetric {
+            cpu_usage,
+            memory_usage,
+        }
+    }
+
+    fn health(healthy: bool, recovering_beats: u32) -> NodeHealth {
+        NodeHealth {
+            healthy,
+            recovering_beats,
+        }
+    }
+
+    #[test]
+    fn reaching_either_limit_marks_the_node_unhealthy() {
+        let policy = policy();
+        assert_eq!(
+            policy.evaluate(health(true, 0), &metric(79.9, 84.9)),
+            health(true, 0)
+        );
+        assert_eq!(
+            policy.evaluate(health(true, 0), &metric(80.0, 10.0)),
+            health(false, 0)
+        );
+        assert_eq!(
+            policy.evaluate(health(true, 0), &metric(10.0, 85.0)),
+            health(false, 0)
+        );
+        /* Even halfway through recovery */
+        assert_eq!(
+            policy.evaluate(health(false, 1), &metric(90.0, 10.0)),
+            health(false, 0)
+        );
+    }
+
+    #[test]
+    fn a_healthy_node_stays_healthy_within_the_watermark_band() {
+        assert_eq!(
+            policy().evaluate(health(true, 0), &metric(70.0, 80.0)),
+            health(true, 0)
+        );
+    }
+
+    #[test]
+    fn recovery_starts_over_within_the_watermark_band() {
+        let policy = policy();
+        assert_eq!(
+            policy.evaluate(health(false, 1), &metric(70.0, 10.0)),
+            health(false, 0)
+        );
+        assert_eq!(
+            policy.evaluate(health(false, 1), &metric(10.0, 75.0)),
+            health(false, 0)
+        );
+    }
+
+    #[test]
+    fn recovers_after_enough_beats_below_both_watermarks() {
+        let policy = policy();
+        let once = policy.evaluate(health(false, 0), &metric(59.9, 69.9));
+        assert_eq!(once, health(false, 1));
+        assert_eq!(policy.evaluate(once, &metric(59.9, 69.9)), health(true, 0));
+    }
+
+    #[test]
+    fn defaults_recover_on_the_first_beat_below_the_limits() {
+        assert_eq!(
+            ThresholdPolicy::default().evaluate(health(false, 0), &metric(79.9, 84.9)),
+            health(true, 0)
+        );
+    }
+}
@@ -0,0 +1,67 @@
+use std::sync::Arc;
+
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{mpsc, watch};
+
+use crate::common::error::ErrorResponse;
+use crate::core::domain::watch::{Change, MembershipEvent};
+use crate::pool::consistent_hash::Ring;
+
+/// Events a watcher can have in flight before the ring events back up.
+pub const WATCHER_BUFFER: usize = 16;
+
+/// Why watch streams end on shutdown, clients should reconnect elsewhere.
+pub const SHUTTING_DOWN: &str = "server is shutting down";
+
+/// Feeds a single watcher the membership of a namespace: a snapshot, then every
+/// change made after it. A watcher falling too far behind starts over from a
+/// fresh snapshot instead of missing changes.
+/// Runs until the watcher goes away, the namespace is deleted or `shutting_down`
+/// flips, which ends the stream with `ErrorResponse::Unavailable`.
+pub async fn run(
+    ring: Arc<Ring>,
+    watcher: mpsc::Sender<Result<MembershipEvent, ErrorResponse>>,
+    mut shutting_down: watch::Receiver<bool>,
+) {
+    let (snapshot, mut events) = ring.subscribe();
+    let mut revision = snapshot.revision;
+    let last = snapshot.change == Change::NamespaceDeleted;
+    if watcher.send(Ok(snapshot)).await.is_err() || last {
+        return;
+    }
+
+    loop {
+        let received = tokio::select! {
+            _ = watcher.closed() => return,
+            /* Also taken if the registry is gone, nothing more would come anyway */
+            _ = async { drop(shutting_down.wait_for(|down| *down).await) } => {
+                let closing = ErrorResponse::Unavailable(SHUTTING_DOWN.to_string());
+                let _ = watcher.send(Err(closing)).await;
+                return;
+            }
+            received = events.recv() => received,
+        };
+
+        match received {
+            /* Already covered by the snapshot */
+            Ok(event) if event.revision <= revision => continue,
+            Ok(event) => {
+                revision = event.revision;
+                let last = event.change == Change::NamespaceDeleted;
+                if watcher.send(Ok(event)).await.is_err() || last {
+                    return;
+                }
+            }
+            Err(RecvError::Lagged(_)) => {
+                let (snapshot, resumed) = ring.subscribe();
+                events = resumed;
+                revision = snapshot.revision;
+                let last = snapshot.change == Change::NamespaceDeleted;
+                if watcher.send(Ok(snapshot)).await.is_err() || last {
+                    return;
+                }
+            }
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
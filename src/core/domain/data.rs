@@ -1,11 +1,103 @@
+use std::collections::HashMap;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::common::error::ErrorResponse;
+use crate::grpc::Instance;
+
+#[derive(Debug, Clone, Copy)]
 pub struct UtilizationMetric {
     pub cpu_usage: f32,
     pub memory_usage: f32,
 }
 
+impl UtilizationMetric {
+    /// Fails unless both usages are percentages, a NaN would compare below every
+    /// limit and keep an overloaded node healthy.
+    pub fn validate(&self) -> Result<(), ErrorResponse> {
+        for (name, usage) in [
+            ("cpu_usage", self.cpu_usage),
+            ("memory_usage", self.memory_usage),
+        ] {
+            if !(0.0..=100.0).contains(&usage) {
+                return Err(ErrorResponse::BadRequest(format!(
+                    "{} must be a percentage between 0 and 100, got {}",
+                    name, usage
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A single point on the consistent hash ring.
+///
+/// A physical server owns `virtual_nodes` points, all sharing the same
+/// `service_id` and endpoint but each with its own ring position `id`.
+/// The ring keeps a single copy per physical server, `id` being its first
+/// position, lookups hand out a copy carrying the position that matched.
 #[derive(Debug, Clone)]
 pub struct Node {
     pub id: u32,
+    /// Handed out on registration, identifies the server in every later call.
+    pub service_id: String,
+    /// Host the service is reachable at, as advertised by the agent.
     pub ip: String,
+    /// Port the service listens on, `0` when unknown.
+    pub port: u32,
     pub healthy: bool,
-}
\ No newline at end of file
+    /// Consecutive heartbeats counted towards recovery, see `HealthPolicy`.
+    pub recovering_beats: u32,
+    /// Free-form labels of the server, e.g. `version` or `zone`.
+    pub metadata: HashMap<String, String>,
+    /// Who registered the server, the only client it takes heartbeats and a
+    /// deregistration from. `None` for nodes listed in `horbo.yml`.
+    pub owner: Option<String>,
+}
+
+impl Node {
+    /// The server as sent over gRPC, heartbeat details left unset.
+    pub fn repr(&self) -> Instance {
+        Instance {
+            service_id: self.service_id.clone(),
+            ip_address: self.ip.clone(),
+            port: self.port,
+            healthy: self.healthy,
+            metadata: self.metadata.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Whether every one of `labels` is set to the same value on the server.
+    pub fn matches(&self, labels: &HashMap<String, String>) -> bool {
+        labels
+            .iter()
+            .all(|(key, value)| self.metadata.get(key) == Some(value))
+    }
+}
+
+/// Latest heartbeat received from a server.
+#[derive(Debug, Clone, Copy)]
+pub struct Heartbeat {
+    pub at: Instant,
+    /// `None` until a heartbeat carried utilization, registration counts as a
+    /// heartbeat without any.
+    pub metric: Option<UtilizationMetric>,
+}
+
+impl Heartbeat {
+    pub fn now() -> Self {
+        Heartbeat {
+            at: Instant::now(),
+            metric: None,
+        }
+    }
+
+    /// Wall clock time of the heartbeat, in milliseconds since the unix epoch.
+    pub fn unix_ms(&self) -> u64 {
+        SystemTime::now()
+            .checked_sub(self.at.elapsed())
+            .and_then(|at| at.duration_since(UNIX_EPOCH).ok())
+            .map(|at| at.as_millis() as u64)
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_MIN_REPORTERS: u32 = 1;
+pub const DEFAULT_REPORT_WINDOW: Duration = Duration::from_secs(30);
+
+/// How much agreement is needed before failure reports take a node down.
+#[derive(Debug, Clone)]
+pub struct FailureQuorum {
+    /// Distinct reporters needed, regardless of namespace size.
+    pub min_reporters: u32,
+    /// Distinct reporters needed as a fraction of the servers in the namespace.
+    pub min_fraction: f32,
+    /// Reports older than this don't count and are dropped.
+    pub window: Duration,
+}
+
+impl Default for FailureQuorum {
+    fn default() -> Self {
+        FailureQuorum {
+            min_reporters: DEFAULT_MIN_REPORTERS,
+            min_fraction: 0.0,
+            window: DEFAULT_REPORT_WINDOW,
+        }
+    }
+}
+
+impl FailureQuorum {
+    /// Distinct reporters required in a namespace of `servers` servers.
+    pub fn required(&self, servers: usize) -> usize {
+        let by_fraction = (self.min_fraction * servers as f32).ceil() as usize;
+        by_fraction.max(self.min_reporters as usize).max(1)
+    }
+}
+
+/// Pending failure reports, per namespace and reported node, keyed by reporter.
+#[derive(Debug, Default)]
+pub struct FailureReports {
+    namespaces: HashMap<String, HashMap<String, HashMap<String, Instant>>>,
+}
+
+impl FailureReports {
+    /// Records a report and returns how many distinct reporters reported the
+    /// node within `window`, this one included. A reporter repeating itself
+    /// only refreshes its report.
+    pub fn record(
+        &mut self,
+        namespace: &str,
+        service_id: &str,
+        reporter: &str,
+        window: Duration,
+    ) -> usize {
+        let reporters = self
+            .namespaces
+            .entry(namespace.to_string())
+            .or_default()
+            .entry(service_id.to_string())
+            .or_default();
+
+        reporters.retain(|_, at| at.elapsed() < window);
+        reporters.insert(reporter.to_string(), Instant::now());
+
+        reporters.len()
+    }
+
+    /// Forgets every report against a node, e.g. once it's been marked unhealthy or left.
+    pub fn clear(&mut self, namespace: &str, service_id: &str) {
+        if let Some(nodes) = self.namespaces.get_mut(namespace) {
+            nodes.remove(service_id);
+            if nodes.is_empty() {
+                self.namespaces.remove(namespace);
+            }
+        }
+    }
+
+    /// Drops reports of a namespace older than `window`.
+    pub fn expire(&mut self, namespace: &str, window: Duration) {
+        if let Some(nodes) = self.namespaces.get_mut(namespace) {
+            for reporters in nodes.values_mut() {
+                reporters.retain(|_, at| at.elapsed() < window);
+            }
+
+            nodes.retain(|_, reporters| !reporters.is_empty());
+            if nodes.is_empty() {
+                self.namespaces.remove(namespace);
+            }
+        }
+    }
+    /// Forgets every report of a namespace, once it's been deleted.
+    pub fn remove_namespace(&mut self, namespace: &str) {
+        self.namespaces.remove(namespace);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_secs(30);
+
+    #[test]
+    fn counts_distinct_reporters() {
+        let mut reports = FailureReports::default();
+
+        assert_eq!(reports.record("payment", "a", "cert:one", WINDOW), 1);
+        assert_eq!(reports.record("payment", "a", "cert:one", WINDOW), 1);
+        assert_eq!(reports.record("payment", "a", "key:agents/2", WINDOW), 2);
+        assert_eq!(reports.record("payment", "b", "cert:one", WINDOW), 1);
+        assert_eq!(reports.record("booking", "a", "cert:one", WINDOW), 1);
+    }
+
+    #[test]
+    fn reports_outside_the_window_dont_count() {
+        let mut reports = FailureReports::default();
+        reports.record("payment", "a", "cert:one", WINDOW);
+
+        assert_eq!(
+            reports.record("payment", "a", "cert:two", Duration::ZERO),
+            1
+        );
+    }
+
+    #[test]
+    fn expire_drops_old_reports_of_the_namespace_only() {
+        let mut reports = FailureReports::default();
+        reports.record("payment", "a", "cert:one", WINDOW);
+        reports.record("booking", "a", "cert:one", WINDOW);
+
+        reports.expire("payment", WINDOW);
+        assert_eq!(reports.record("payment", "a", "cert:two", WINDOW), 2);
+
+        reports.expire("payment", Duration::ZERO);
+        assert!(!reports.namespaces.contains_key("payment"));
+        assert_eq!(reports.record("booking", "a", "cert:two", WINDOW), 2);
+    }
+
+    #[test]
+    fn clear_forgets_a_single_node() {
+        let mut reports = FailureReports::default();
+        reports.record("payment", "a", "cert:one", WINDOW);
+        reports.record("payment", "b", "cert:one", WINDOW);
+
+        reports.clear("payment", "a");
+        assert_eq!(reports.record("payment", "a", "cert:two", WINDOW), 1);
+        assert_eq!(reports.record("payment", "b", "cert:two", WINDOW), 2);
+    }
+
+    #[test]
+    fn quorum_takes_the_larger_of_count_and_fraction() {
+        let quorum = |min_reporters, min_fraction| FailureQuorum {
+            min_reporters,
+            min_fraction,
+            window: WINDOW,
+        };
+
+        assert_eq!(quorum(2, 0.0).required(10), 2);
+        assert_eq!(quorum(2, 0.5).required(10), 5);
+        assert_eq!(quorum(2, 0.5).required(3), 2);
+        assert_eq!(quorum(1, 0.25).required(5), 2);
+        assert_eq!(quorum(0, 0.0).required(0), 1);
+    }
+}
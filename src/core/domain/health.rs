@@ -0,0 +1,19 @@
+use std::fmt::Debug;
+
+use crate::core::domain::data::UtilizationMetric;
+
+/// Health bookkeeping of a single server, shared by all of its virtual nodes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NodeHealth {
+    pub healthy: bool,
+    /// Consecutive heartbeats that qualified for recovery while unhealthy.
+    pub recovering_beats: u32,
+}
+
+/// Decides whether a node is healthy based on the metrics it reports.
+///
+/// Implementations get the node's current state so they can apply hysteresis
+/// instead of flipping on every heartbeat.
+pub trait HealthPolicy: Debug + Send + Sync {
+    fn evaluate(&self, current: NodeHealth, metric: &UtilizationMetric) -> NodeHealth;
+}
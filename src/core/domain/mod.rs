@@ -1,2 +1,6 @@
 pub mod server;
-pub mod data;
\ No newline at end of file
+pub mod data;
+pub mod failure_report;
+pub mod health;
+pub mod unhealthy;
+pub mod watch;
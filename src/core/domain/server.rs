@@ -1,30 +1,87 @@
 use crate::{
-    common::error::ErrorResponse, core::domain::data::UtilizationMetric, grpc::{AgentRegistrationResponse, HeartbeatResponse, LookupResponse},
+    common::error::ErrorResponse,
+    core::domain::data::UtilizationMetric,
+    core::domain::watch::MembershipEvent,
+    core::schema::NamespaceDefinition,
+    grpc::{
+        AgentRegistrationResponse, HeartbeatResponse, ListInstancesResponse,
+        ListNamespacesResponse, LookupResponse, NamespaceDescription, NodeMap,
+    },
 };
+use std::collections::HashMap;
+use tokio::sync::mpsc;
 
+// Only implemented and awaited inside this crate, callers never need a `Send` bound on it
+#[allow(async_fn_in_trait)]
 pub trait ServiceDiscoveryUsecase {
     async fn register_node(
         &self,
         namespace: String,
         ip_address: String,
+        port: u32,
+        metadata: HashMap<String, String>,
+        owner: String,
     ) -> Result<AgentRegistrationResponse, ErrorResponse>;
 
+    async fn deregister_node(
+        &self,
+        namespace: String,
+        service_id: String,
+        owner: Option<String>,
+    ) -> Result<(), ErrorResponse>;
+
     async fn node_heartbeat(
         &self,
         namespace: String,
-        ip_address: String,
+        service_id: String,
         metric: UtilizationMetric,
+        owner: Option<String>,
     ) -> Result<HeartbeatResponse, ErrorResponse>;
 
     async fn service_lookup(
         &self,
         namespace: String,
         client_ip_address: String,
+        selector: HashMap<String, String>,
     ) -> Result<LookupResponse, ErrorResponse>;
 
     async fn mark_node_unhealthy(
         &self,
         namespace: String,
-        ip_address: String,
+        node: String,
+        reporter: String,
     ) -> Result<(), ErrorResponse>;
+
+    async fn reap_silent_nodes(&self) -> Result<(), ErrorResponse>;
+
+    async fn create_namespace(
+        &self,
+        namespace: String,
+        definition: NamespaceDefinition,
+    ) -> Result<NamespaceDescription, ErrorResponse>;
+
+    async fn list_namespaces(&self) -> Result<ListNamespacesResponse, ErrorResponse>;
+
+    async fn describe_namespace(
+        &self,
+        namespace: String,
+    ) -> Result<NamespaceDescription, ErrorResponse>;
+
+    async fn delete_namespace(&self, namespace: String) -> Result<(), ErrorResponse>;
+
+    async fn checkpoint(&self) -> Result<(), ErrorResponse>;
+
+    async fn unhealthy_services(&self) -> Result<Vec<NodeMap>, ErrorResponse>;
+
+    async fn list_instances(
+        &self,
+        namespace: String,
+        healthy: Option<bool>,
+        labels: HashMap<String, String>,
+    ) -> Result<ListInstancesResponse, ErrorResponse>;
+
+    async fn watch(
+        &self,
+        namespace: String,
+    ) -> Result<mpsc::Receiver<Result<MembershipEvent, ErrorResponse>>, ErrorResponse>;
 }
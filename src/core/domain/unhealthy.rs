@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+
+use crate::grpc::{Node as NodeGrpc, NodeMap};
+
+#[derive(Debug, Clone)]
+pub struct UnhealthyEntry {
+    pub service_id: String,
+    pub ip: String,
+}
+
+/// Nodes currently considered unhealthy, grouped by namespace.
+///
+/// This is what gets gossiped back to agents in every heartbeat response, so
+/// a node must leave the set as soon as it recovers or leaves the ring.
+#[derive(Debug, Default)]
+pub struct UnhealthySet {
+    namespaces: HashMap<String, HashMap<String, UnhealthyEntry>>,
+}
+
+impl UnhealthySet {
+    /// Adds a node to the set, returns `false` if it was already in it.
+    pub fn enter(&mut self, namespace: &str, service_id: &str, ip: &str) -> bool {
+        let entries = self.namespaces.entry(namespace.to_string()).or_default();
+        if entries.contains_key(service_id) {
+            return false;
+        }
+
+        entries.insert(
+            service_id.to_string(),
+            UnhealthyEntry {
+                service_id: service_id.to_string(),
+                ip: ip.to_string(),
+            },
+        );
+
+        true
+    }
+
+    /// Removes a node from the set, returns the entry if it was in it.
+    pub fn exit(&mut self, namespace: &str, service_id: &str) -> Option<UnhealthyEntry> {
+        let entries = self.namespaces.get_mut(namespace)?;
+        let entry = entries.remove(service_id);
+
+        if entries.is_empty() {
+            self.namespaces.remove(namespace);
+        }
+
+        entry
+    }
+
+    /// Drops every node of a namespace, once it's been deleted.
+    pub fn remove_namespace(&mut self, namespace: &str) {
+        self.namespaces.remove(namespace);
+    }
+
+    pub fn contains(&self, namespace: &str, service_id: &str) -> bool {
+        match self.namespaces.get(namespace) {
+            Some(entries) => entries.contains_key(service_id),
+            None => false,
+        }
+    }
+
+    /// All unhealthy nodes as sent in `HeartbeatResponse`, one `NodeMap` per namespace.
+    pub fn repr(&self) -> Vec<NodeMap> {
+        let mut result: Vec<NodeMap> = Vec::new();
+
+        for (namespace, entries) in self.namespaces.iter() {
+            result.push(NodeMap {
+                namespace: namespace.clone(),
+                node: entries
+                    .values()
+                    .map(|entry| NodeGrpc {
+                        id: entry.service_id.clone(),
+                        ip_address: entry.ip.clone(),
+                        namespace: namespace.clone(),
+                    })
+                    .collect(),
+            });
+        }
+
+        result
+    }
+}
@@ -0,0 +1,49 @@
+use crate::core::domain::data::Node;
+use crate::grpc::{WatchEvent, WatchEventType};
+
+/// What happened to the nodes of a `MembershipEvent`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Change {
+    /// Every node of the namespace as of `revision`, what a watch starts with.
+    Snapshot,
+    Added,
+    Removed,
+    HealthChanged,
+    /// A node registered again under the same address with other labels.
+    MetadataChanged,
+    /// The namespace is gone, nothing follows.
+    NamespaceDeleted,
+}
+
+/// A change of namespace membership as seen by watchers.
+///
+/// Revisions are per namespace and grow by one with every change, so a watcher
+/// can tell which events a snapshot already covers.
+#[derive(Debug, Clone)]
+pub struct MembershipEvent {
+    pub namespace: String,
+    pub revision: u64,
+    pub change: Change,
+    pub nodes: Vec<Node>,
+}
+
+impl MembershipEvent {
+    /// The event as streamed to `Watch` callers.
+    pub fn repr(&self) -> WatchEvent {
+        let r#type = match self.change {
+            Change::Snapshot => WatchEventType::Snapshot,
+            Change::Added => WatchEventType::Added,
+            Change::Removed => WatchEventType::Removed,
+            Change::HealthChanged => WatchEventType::HealthChanged,
+            Change::MetadataChanged => WatchEventType::MetadataChanged,
+            Change::NamespaceDeleted => WatchEventType::NamespaceDeleted,
+        };
+
+        WatchEvent {
+            namespace: self.namespace.clone(),
+            revision: self.revision,
+            r#type: r#type as i32,
+            instances: self.nodes.iter().map(|node| node.repr()).collect(),
+        }
+    }
+}
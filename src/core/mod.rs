@@ -1,3 +1,4 @@
 pub mod schema;
+pub mod validate;
 pub mod application;
 pub mod domain;
\ No newline at end of file
@@ -1,28 +1,1111 @@
-use std::{collections::HashMap, fs, io};
+use std::{
+    collections::HashMap, env, fmt, fs, net::SocketAddr, path::Path, sync::Arc, time::Duration,
+};
 
-use serde::Deserialize;
+use serde::de::value::{MapAccessDeserializer, SeqAccessDeserializer};
+use serde::de::{MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use tracing_subscriber::EnvFilter;
+
+use crate::auth::policy::Operation;
+use crate::common::error::{ConfigError, ConfigProblem};
+use crate::core::application::reaper::REAP_INTERVAL;
+use crate::core::application::shutdown::DEFAULT_GRACE_PERIOD;
+use crate::core::application::snapshotter::DEFAULT_SNAPSHOT_INTERVAL;
+use crate::core::application::threshold_policy::{
+    ThresholdPolicy, DEFAULT_CPU_LIMIT, DEFAULT_MEMORY_LIMIT,
+};
+use crate::core::domain::failure_report::{
+    FailureQuorum, DEFAULT_MIN_REPORTERS, DEFAULT_REPORT_WINDOW,
+};
+use crate::core::validate::Validator;
+use crate::grpc::NamespaceSettings;
+use crate::metrics::protocol::MetricsProtocol;
+use crate::pool::consistent_hash::{
+    RingSettings, DEFAULT_EVICTION_TTL, DEFAULT_HEARTBEAT_TIMEOUT, DEFAULT_VIRTUAL_NODES,
+};
+use crate::utils::addr::split_host_port;
+
+/// Config file used when neither `--config` nor `HORBO_CONFIG` is given.
+pub const DEFAULT_CONFIG_PATH: &str = "horbo.yml";
+
+/// Prefix of environment variables overriding config settings, path segments
+/// are separated by `__`, e.g. `HORBO__SERVER__LISTEN=0.0.0.0:50051`.
+const ENV_OVERRIDE_PREFIX: &str = "HORBO__";
 
 #[derive(Debug, Deserialize)]
 pub struct ServiceDefinition {
-    pub services: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    pub server: ServerDefinition,
+    #[serde(default, deserialize_with = "deserialize_services")]
+    pub services: HashMap<String, NamespaceDefinition>,
+    /// Where registry state is persisted, nothing survives a restart when left out.
+    #[serde(default)]
+    pub storage: Option<StorageDefinition>,
+    /// UDP metrics ingestion, agents can only heartbeat over gRPC when left out.
+    #[serde(default)]
+    pub metrics: Option<MetricsDefinition>,
+    /// Who may call what, every gRPC call is allowed when left out.
+    #[serde(default)]
+    pub auth: Option<AuthDefinition>,
+    /// HTTP/JSON API served next to gRPC, only gRPC is served when left out.
+    #[serde(default)]
+    pub http: Option<HttpDefinition>,
+    #[serde(default)]
+    pub logging: LoggingDefinition,
+}
+
+/// Where log lines go is up to the process supervisor, they're always written to stdout.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LoggingDefinition {
+    /// Either a level (`error` to `trace`) or per-target directives, e.g.
+    /// `info,audit=info,h2=warn`.
+    #[serde(default = "default_log_level")]
+    pub level: String,
+    #[serde(default)]
+    pub format: LogFormat,
+}
+
+impl Default for LoggingDefinition {
+    fn default() -> Self {
+        LoggingDefinition {
+            level: default_log_level(),
+            format: LogFormat::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// One human readable line per event.
+    #[default]
+    Text,
+    /// One JSON object per event, with the fields of its span.
+    Json,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct HttpDefinition {
+    /// Address the HTTP API binds to, it's served in plaintext.
+    #[serde(default = "default_http_listen")]
+    pub listen: String,
+    /// Also serves the endpoints changing the registry, which need an `auth:` block.
+    #[serde(default)]
+    pub admin: bool,
+}
+
+impl HttpDefinition {
+    /// Only valid on a validated config.
+    pub fn listen_addr(&self) -> SocketAddr {
+        self.listen
+            .parse()
+            .unwrap_or_else(|_| default_http_listen_addr())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ServerDefinition {
+    /// Address the gRPC server binds to, the UDP metrics listener shares its host.
+    #[serde(default = "default_listen")]
+    pub listen: String,
+    /// Seconds between checks for silent nodes.
+    #[serde(default = "default_reap_interval_secs")]
+    pub reap_interval_secs: u64,
+    /// Seconds calls in flight get to finish on SIGTERM or SIGINT before the
+    /// process exits anyway.
+    #[serde(default = "default_shutdown_grace_secs")]
+    pub shutdown_grace_secs: u64,
+    #[serde(default)]
+    pub tls: TlsDefinition,
+}
+
+impl Default for ServerDefinition {
+    fn default() -> Self {
+        ServerDefinition {
+            listen: default_listen(),
+            reap_interval_secs: default_reap_interval_secs(),
+            shutdown_grace_secs: default_shutdown_grace_secs(),
+            tls: TlsDefinition::default(),
+        }
+    }
+}
+
+impl ServerDefinition {
+    /// Only valid on a validated config.
+    pub fn listen_addr(&self) -> SocketAddr {
+        self.listen
+            .parse()
+            .unwrap_or_else(|_| default_listen_addr())
+    }
+}
+
+/// How the gRPC server talks to clients.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TlsMode {
+    /// Clients must present a certificate signed by `client_ca`.
+    #[default]
+    Mtls,
+    /// Server certificate only, any client may connect.
+    Tls,
+    /// No encryption at all, for local development only.
+    Plaintext,
+}
+
+/// PEM files of the gRPC server, which of them are needed depends on `mode`.
+/// They are reloaded whenever they change on disk.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TlsDefinition {
+    #[serde(default)]
+    pub mode: TlsMode,
+    #[serde(default = "default_tls_cert")]
+    pub cert: String,
+    #[serde(default = "default_tls_key")]
+    pub key: String,
+    #[serde(default = "default_tls_client_ca")]
+    pub client_ca: String,
+}
+
+impl Default for TlsDefinition {
+    fn default() -> Self {
+        TlsDefinition {
+            mode: TlsMode::default(),
+            cert: default_tls_cert(),
+            key: default_tls_key(),
+            client_ca: default_tls_client_ca(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuthDefinition {
+    #[serde(default)]
+    pub principals: Vec<PrincipalDefinition>,
+}
+
+/// A named identity and what it's allowed to do.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PrincipalDefinition {
+    pub name: String,
+    /// Matched against DNS and URI SANs, then the CN, of mTLS client certificates.
+    #[serde(default)]
+    pub cert_names: Vec<String>,
+    /// Accepted in the `x-api-key` metadata, or the `api_key` of a registration.
+    #[serde(default)]
+    pub api_keys: Vec<String>,
+    /// Namespaces the principal may touch, `*` for all of them.
+    #[serde(default)]
+    pub namespaces: Vec<String>,
+    #[serde(default)]
+    pub operations: Vec<Operation>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StorageDefinition {
+    /// Directory holding the write-ahead log and the snapshot.
+    pub dir: String,
+    /// Seconds between snapshots, the log is truncated after each one.
+    #[serde(default = "default_snapshot_every_secs")]
+    pub snapshot_every_secs: u64,
+}
+
+/// Framing of the UDP metrics protocol, see `metrics::protocol`.
+#[derive(Debug, Deserialize)]
+pub struct MetricsDefinition {
+    /// Port horbo receives metrics datagrams on.
+    pub dest_port: String,
+    /// Hex encoded byte every frame starts with.
+    pub start_flag: String,
+    /// Shared secret every frame must carry in its `key` field, other frames are dropped.
+    pub key: String,
+    pub message_length: MessageLengthDefinition,
+    pub protocol_version: u8,
+    pub message_body: MessageBodyDefinition,
+    /// Left over from the first metrics layout, still accepted but not used.
+    #[serde(default)]
+    pub version: Option<serde_yaml::Value>,
+    /// Left over from the first metrics layout, still accepted but not used.
+    #[serde(default)]
+    pub source_port: Option<serde_yaml::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MessageLengthDefinition {
+    /// Width of the big endian length field, in bytes.
+    pub reserved_bytes: u8,
+}
+
+/// Ids of the fields a frame body is made of.
+#[derive(Debug, Deserialize)]
+pub struct MessageBodyDefinition {
+    pub cpu: FieldDefinition,
+    pub memory: FieldDefinition,
+    #[serde(default = "default_namespace_field")]
+    pub namespace: FieldDefinition,
+    #[serde(default = "default_service_id_field")]
+    pub service_id: FieldDefinition,
+    #[serde(default = "default_key_field")]
+    pub key: FieldDefinition,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FieldDefinition {
+    pub id: u8,
+}
+
+/// Key of the sample `metrics:` block, anyone reading the docs would know it.
+const PLACEHOLDER_METRICS_KEY: &str = "change-me";
+
+impl MetricsDefinition {
+    fn validate(&self, v: &mut Validator) {
+        v.check(
+            self.dest_port.parse::<u16>().is_ok_and(|port| port > 0),
+            "metrics.dest_port",
+            "must be a port number",
+        );
+        v.check(
+            u8::from_str_radix(self.start_flag.trim_start_matches("0x"), 16).is_ok(),
+            "metrics.start_flag",
+            "must be a single hex encoded byte",
+        );
+        /* Field values carry their size in a single byte */
+        v.check(
+            !self.key.is_empty() && self.key.len() <= u8::MAX as usize,
+            "metrics.key",
+            "must be between 1 and 255 bytes",
+        );
+        v.check(
+            self.key != PLACEHOLDER_METRICS_KEY,
+            "metrics.key",
+            "must be replaced by a secret of your own",
+        );
+        v.check(
+            (1..=4).contains(&self.message_length.reserved_bytes),
+            "metrics.message_length.reserved_bytes",
+            "must be between 1 and 4",
+        );
+
+        let body = &self.message_body;
+        let mut ids = vec![
+            body.cpu.id,
+            body.memory.id,
+            body.namespace.id,
+            body.service_id.id,
+            body.key.id,
+        ];
+        ids.sort();
+        ids.dedup();
+        v.check(
+            ids.len() == 5,
+            "metrics.message_body",
+            "field ids must be distinct",
+        );
+    }
+
+    /// Settings present in the file that no longer do anything.
+    pub fn unused(&self) -> Vec<&'static str> {
+        let mut unused = Vec::new();
+        if self.version.is_some() {
+            unused.push("metrics.version");
+        }
+        if self.source_port.is_some() {
+            unused.push("metrics.source_port");
+        }
+        unused
+    }
+
+    /// Protocol parameters, only valid on a validated config.
+    pub fn protocol(&self) -> MetricsProtocol {
+        let body = &self.message_body;
+
+        MetricsProtocol {
+            port: self.dest_port.parse().unwrap_or_default(),
+            start_flag: u8::from_str_radix(self.start_flag.trim_start_matches("0x"), 16)
+                .unwrap_or_default(),
+            length_bytes: self.message_length.reserved_bytes as usize,
+            version: self.protocol_version,
+            cpu_id: body.cpu.id,
+            memory_id: body.memory.id,
+            namespace_id: body.namespace.id,
+            service_id_id: body.service_id.id,
+            key_id: body.key.id,
+            key: self.key.as_bytes().to_vec(),
+        }
+    }
 }
 
-fn load_services_definition(filepath: &str) -> Result<ServiceDefinition, io::Error> {
-    let contents = fs::read_to_string(filepath)?;
-    let root: ServiceDefinition = serde_yaml::from_str(&contents)
-        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NamespaceDefinition {
+    /// Nodes known ahead of time, agents may still register at runtime.
+    #[serde(default)]
+    pub nodes: Vec<String>,
+    /// Number of points each server owns on the ring.
+    #[serde(default = "default_virtual_nodes")]
+    pub virtual_nodes: u32,
+    #[serde(default)]
+    pub heartbeat: HeartbeatDefinition,
+    #[serde(default)]
+    pub health: HealthDefinition,
+    #[serde(default)]
+    pub failure_report: FailureReportDefinition,
+}
 
-    Ok(root)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeartbeatDefinition {
+    /// Seconds without a heartbeat before a node is marked unhealthy.
+    #[serde(default = "default_unhealthy_after_secs")]
+    pub unhealthy_after_secs: u64,
+    /// Seconds without a heartbeat before a node is evicted from the ring.
+    #[serde(default = "default_evict_after_secs")]
+    pub evict_after_secs: u64,
 }
 
-pub fn init() -> Result<ServiceDefinition, io::Error> {
-    let filepath = "horbo.yml";
-    match load_services_definition(filepath) {
-        Ok(definition) => {
-            Ok(definition)
+impl Default for HeartbeatDefinition {
+    fn default() -> Self {
+        HeartbeatDefinition {
+            unhealthy_after_secs: default_unhealthy_after_secs(),
+            evict_after_secs: default_evict_after_secs(),
         }
-        Err(e) => {
-            Err(e)
+    }
+}
+
+/// Limits of the threshold health policy. Recovery watermarks default to the
+/// limits themselves, which means no hysteresis.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthDefinition {
+    #[serde(default = "default_cpu_limit")]
+    pub cpu_limit: f32,
+    #[serde(default = "default_memory_limit")]
+    pub memory_limit: f32,
+    pub cpu_recover_below: Option<f32>,
+    pub memory_recover_below: Option<f32>,
+    /// Heartbeats below both watermarks before an unhealthy node is healthy again.
+    #[serde(default = "default_recover_after_beats")]
+    pub recover_after_beats: u32,
+}
+
+impl Default for HealthDefinition {
+    fn default() -> Self {
+        HealthDefinition {
+            cpu_limit: default_cpu_limit(),
+            memory_limit: default_memory_limit(),
+            cpu_recover_below: None,
+            memory_recover_below: None,
+            recover_after_beats: default_recover_after_beats(),
         }
     }
-}
\ No newline at end of file
+}
+
+impl HealthDefinition {
+    pub fn policy(&self) -> ThresholdPolicy {
+        ThresholdPolicy {
+            cpu_limit: self.cpu_limit,
+            memory_limit: self.memory_limit,
+            cpu_recover_below: self.cpu_recover_below.unwrap_or(self.cpu_limit),
+            memory_recover_below: self.memory_recover_below.unwrap_or(self.memory_limit),
+            recover_after_beats: self.recover_after_beats.max(1),
+        }
+    }
+}
+
+/// Quorum of `ServiceFailureReport` callers needed to mark a node unhealthy.
+/// The stricter of `min_reporters` and `min_fraction` of the namespace applies.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailureReportDefinition {
+    #[serde(default = "default_min_reporters")]
+    pub min_reporters: u32,
+    #[serde(default)]
+    pub min_fraction: f32,
+    /// Seconds a report counts towards the quorum.
+    #[serde(default = "default_report_window_secs")]
+    pub window_secs: u64,
+}
+
+impl Default for FailureReportDefinition {
+    fn default() -> Self {
+        FailureReportDefinition {
+            min_reporters: default_min_reporters(),
+            min_fraction: 0.0,
+            window_secs: default_report_window_secs(),
+        }
+    }
+}
+
+impl FailureReportDefinition {
+    pub fn quorum(&self) -> FailureQuorum {
+        FailureQuorum {
+            min_reporters: self.min_reporters,
+            min_fraction: self.min_fraction,
+            window: Duration::from_secs(self.window_secs),
+        }
+    }
+}
+
+/// A `services:` entry, either a namespace definition or the bare list of its
+/// nodes that configs were written with before namespaces had settings.
+#[derive(Debug)]
+enum NamespaceEntry {
+    Nodes(Vec<String>),
+    Definition(NamespaceDefinition),
+}
+
+/// Same as `#[serde(untagged)]`, except errors within a definition keep their
+/// field and line instead of becoming "data did not match any variant".
+impl<'de> Deserialize<'de> for NamespaceEntry {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct EntryVisitor;
+
+        impl<'de> Visitor<'de> for EntryVisitor {
+            type Value = NamespaceEntry;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a namespace definition or a list of nodes")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+                Vec::deserialize(SeqAccessDeserializer::new(seq)).map(NamespaceEntry::Nodes)
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                NamespaceDefinition::deserialize(MapAccessDeserializer::new(map))
+                    .map(NamespaceEntry::Definition)
+            }
+        }
+
+        deserializer.deserialize_any(EntryVisitor)
+    }
+}
+
+impl From<NamespaceEntry> for NamespaceDefinition {
+    fn from(entry: NamespaceEntry) -> Self {
+        match entry {
+            NamespaceEntry::Nodes(nodes) => NamespaceDefinition {
+                nodes,
+                ..Default::default()
+            },
+            NamespaceEntry::Definition(definition) => definition,
+        }
+    }
+}
+
+fn deserialize_services<'de, D>(
+    deserializer: D,
+) -> Result<HashMap<String, NamespaceDefinition>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let entries: HashMap<String, NamespaceEntry> = HashMap::deserialize(deserializer)?;
+    Ok(entries
+        .into_iter()
+        .map(|(name, entry)| (name, entry.into()))
+        .collect())
+}
+
+impl Default for NamespaceDefinition {
+    fn default() -> Self {
+        NamespaceDefinition {
+            nodes: Vec::new(),
+            virtual_nodes: default_virtual_nodes(),
+            heartbeat: HeartbeatDefinition::default(),
+            health: HealthDefinition::default(),
+            failure_report: FailureReportDefinition::default(),
+        }
+    }
+}
+
+impl NamespaceDefinition {
+    /// Builds a definition out of gRPC settings, proto3 zero values fall back to defaults.
+    pub fn from_grpc(settings: Option<NamespaceSettings>) -> Self {
+        let mut definition = NamespaceDefinition::default();
+        let settings = match settings {
+            Some(settings) => settings,
+            None => return definition,
+        };
+
+        if settings.virtual_nodes > 0 {
+            definition.virtual_nodes = settings.virtual_nodes;
+        }
+        if settings.unhealthy_after_secs > 0 {
+            definition.heartbeat.unhealthy_after_secs = settings.unhealthy_after_secs;
+        }
+        if settings.evict_after_secs > 0 {
+            definition.heartbeat.evict_after_secs = settings.evict_after_secs;
+        }
+        if settings.cpu_limit > 0.0 {
+            definition.health.cpu_limit = settings.cpu_limit;
+        }
+        if settings.memory_limit > 0.0 {
+            definition.health.memory_limit = settings.memory_limit;
+        }
+        if settings.cpu_recover_below > 0.0 {
+            definition.health.cpu_recover_below = Some(settings.cpu_recover_below);
+        }
+        if settings.memory_recover_below > 0.0 {
+            definition.health.memory_recover_below = Some(settings.memory_recover_below);
+        }
+        if settings.recover_after_beats > 0 {
+            definition.health.recover_after_beats = settings.recover_after_beats;
+        }
+        if settings.min_reporters > 0 {
+            definition.failure_report.min_reporters = settings.min_reporters;
+        }
+        definition.failure_report.min_fraction = settings.min_fraction;
+        if settings.report_window_secs > 0 {
+            definition.failure_report.window_secs = settings.report_window_secs;
+        }
+
+        definition
+    }
+
+    /// Effective settings as sent over gRPC.
+    pub fn repr(&self) -> NamespaceSettings {
+        let policy = self.health.policy();
+
+        NamespaceSettings {
+            virtual_nodes: self.virtual_nodes,
+            unhealthy_after_secs: self.heartbeat.unhealthy_after_secs,
+            evict_after_secs: self.heartbeat.evict_after_secs,
+            cpu_limit: policy.cpu_limit,
+            memory_limit: policy.memory_limit,
+            cpu_recover_below: policy.cpu_recover_below,
+            memory_recover_below: policy.memory_recover_below,
+            recover_after_beats: policy.recover_after_beats,
+            min_reporters: self.failure_report.min_reporters,
+            min_fraction: self.failure_report.min_fraction,
+            report_window_secs: self.failure_report.window_secs,
+        }
+    }
+
+    /// Checks every setting, `field` is where the namespace sits in the config.
+    pub fn validate(&self, field: &str, v: &mut Validator) {
+        for (i, node) in self.nodes.iter().enumerate() {
+            v.check(
+                split_host_port(node).is_some_and(|(host, _)| !host.trim().is_empty())
+                    && !node.contains(char::is_whitespace),
+                &format!("{}.nodes[{}]", field, i),
+                "must be a host or host:port",
+            );
+        }
+        v.check(
+            self.virtual_nodes >= 1,
+            &format!("{}.virtual_nodes", field),
+            "must be at least 1",
+        );
+
+        let heartbeat = &self.heartbeat;
+        v.check(
+            heartbeat.unhealthy_after_secs > 0,
+            &format!("{}.heartbeat.unhealthy_after_secs", field),
+            "must be greater than 0",
+        );
+        v.check(
+            heartbeat.evict_after_secs >= heartbeat.unhealthy_after_secs,
+            &format!("{}.heartbeat.evict_after_secs", field),
+            "must not be shorter than unhealthy_after_secs",
+        );
+
+        let health = &self.health;
+        for (name, limit, recover_below) in [
+            ("cpu", health.cpu_limit, health.cpu_recover_below),
+            ("memory", health.memory_limit, health.memory_recover_below),
+        ] {
+            v.check(
+                limit > 0.0 && limit <= 100.0,
+                &format!("{}.health.{}_limit", field, name),
+                "must be a percentage above 0",
+            );
+            if let Some(recover_below) = recover_below {
+                v.check(
+                    recover_below > 0.0 && recover_below <= limit,
+                    &format!("{}.health.{}_recover_below", field, name),
+                    &format!("must be above 0 and at most {}_limit", name),
+                );
+            }
+        }
+        v.check(
+            health.recover_after_beats >= 1,
+            &format!("{}.health.recover_after_beats", field),
+            "must be at least 1",
+        );
+
+        let failure_report = &self.failure_report;
+        v.check(
+            failure_report.min_reporters >= 1,
+            &format!("{}.failure_report.min_reporters", field),
+            "must be at least 1",
+        );
+        v.check(
+            (0.0..=1.0).contains(&failure_report.min_fraction),
+            &format!("{}.failure_report.min_fraction", field),
+            "must be between 0 and 1",
+        );
+        v.check(
+            failure_report.window_secs > 0,
+            &format!("{}.failure_report.window_secs", field),
+            "must be greater than 0",
+        );
+    }
+
+    pub fn ring_settings(&self) -> RingSettings {
+        RingSettings {
+            virtual_nodes: self.virtual_nodes,
+            heartbeat_timeout: Duration::from_secs(self.heartbeat.unhealthy_after_secs),
+            eviction_ttl: Duration::from_secs(self.heartbeat.evict_after_secs),
+            health_policy: Arc::new(self.health.policy()),
+            failure_quorum: self.failure_report.quorum(),
+        }
+    }
+}
+
+fn default_virtual_nodes() -> u32 {
+    DEFAULT_VIRTUAL_NODES
+}
+
+fn default_cpu_limit() -> f32 {
+    DEFAULT_CPU_LIMIT
+}
+
+fn default_memory_limit() -> f32 {
+    DEFAULT_MEMORY_LIMIT
+}
+
+fn default_recover_after_beats() -> u32 {
+    1
+}
+
+fn default_min_reporters() -> u32 {
+    DEFAULT_MIN_REPORTERS
+}
+
+fn default_report_window_secs() -> u64 {
+    DEFAULT_REPORT_WINDOW.as_secs()
+}
+
+fn default_unhealthy_after_secs() -> u64 {
+    DEFAULT_HEARTBEAT_TIMEOUT.as_secs()
+}
+
+fn default_evict_after_secs() -> u64 {
+    DEFAULT_EVICTION_TTL.as_secs()
+}
+
+fn default_namespace_field() -> FieldDefinition {
+    FieldDefinition { id: 3 }
+}
+
+fn default_service_id_field() -> FieldDefinition {
+    FieldDefinition { id: 4 }
+}
+
+fn default_key_field() -> FieldDefinition {
+    FieldDefinition { id: 5 }
+}
+
+fn default_snapshot_every_secs() -> u64 {
+    DEFAULT_SNAPSHOT_INTERVAL.as_secs()
+}
+
+fn default_listen_addr() -> SocketAddr {
+    SocketAddr::from((std::net::Ipv6Addr::LOCALHOST, 50051))
+}
+
+fn default_http_listen_addr() -> SocketAddr {
+    SocketAddr::from((std::net::Ipv6Addr::LOCALHOST, 8080))
+}
+
+fn default_http_listen() -> String {
+    default_http_listen_addr().to_string()
+}
+
+fn default_log_level() -> String {
+    "info".to_string()
+}
+
+fn default_listen() -> String {
+    default_listen_addr().to_string()
+}
+
+fn default_shutdown_grace_secs() -> u64 {
+    DEFAULT_GRACE_PERIOD.as_secs()
+}
+
+fn default_reap_interval_secs() -> u64 {
+    REAP_INTERVAL.as_secs()
+}
+
+fn default_tls_cert() -> String {
+    "./keys/server.crt".to_string()
+}
+
+fn default_tls_key() -> String {
+    "./keys/server.key".to_string()
+}
+
+fn default_tls_client_ca() -> String {
+    "./keys/ca.crt".to_string()
+}
+
+impl ServiceDefinition {
+    pub fn validate(&self, v: &mut Validator) {
+        v.check(
+            EnvFilter::try_new(&self.logging.level).is_ok(),
+            "logging.level",
+            "must be a level (error, warn, info, debug, trace) or target=level directives",
+        );
+        v.check(
+            self.server.listen.parse::<SocketAddr>().is_ok(),
+            "server.listen",
+            "must be an ip:port socket address, e.g. 0.0.0.0:50051",
+        );
+        v.check(
+            self.server.reap_interval_secs > 0,
+            "server.reap_interval_secs",
+            "must be greater than 0",
+        );
+
+        let tls = &self.server.tls;
+        let required: Vec<(&str, &String)> = match tls.mode {
+            TlsMode::Mtls => vec![
+                ("cert", &tls.cert),
+                ("key", &tls.key),
+                ("client_ca", &tls.client_ca),
+            ],
+            TlsMode::Tls => vec![("cert", &tls.cert), ("key", &tls.key)],
+            TlsMode::Plaintext => vec![],
+        };
+        for (name, path) in required {
+            if !Path::new(path).is_file() {
+                v.report(
+                    &format!("server.tls.{}", name),
+                    format!("{} doesn't exist or isn't a file", path),
+                );
+            }
+        }
+
+        let mut names: Vec<&String> = self.services.keys().collect();
+        names.sort();
+        for name in names {
+            let field = format!("services.{}", name);
+            v.check(!name.trim().is_empty(), &field, "name can't be empty");
+            self.services[name].validate(&field, v);
+        }
+
+        if let Some(storage) = &self.storage {
+            v.check(
+                !storage.dir.is_empty(),
+                "storage.dir",
+                "must be a directory",
+            );
+            v.check(
+                storage.snapshot_every_secs > 0,
+                "storage.snapshot_every_secs",
+                "must be greater than 0",
+            );
+        }
+
+        if let Some(metrics) = &self.metrics {
+            metrics.validate(v);
+        }
+
+        if let Some(http) = &self.http {
+            v.check(
+                http.listen.parse::<SocketAddr>().is_ok(),
+                "http.listen",
+                "must be an ip:port socket address, e.g. 127.0.0.1:8080",
+            );
+            v.check(
+                http.listen != self.server.listen,
+                "http.listen",
+                "can't be the same address as server.listen",
+            );
+            v.check(
+                !http.admin || self.auth.is_some(),
+                "http.admin",
+                "needs an auth block, admin endpoints would be open to anyone otherwise",
+            );
+        }
+
+        if let Some(auth) = &self.auth {
+            let mut names: Vec<&str> = Vec::new();
+            for (i, principal) in auth.principals.iter().enumerate() {
+                let field = format!("auth.principals[{}]", i);
+                v.check(
+                    !principal.name.is_empty() && !names.contains(&principal.name.as_str()),
+                    &field,
+                    "name must be set and unique",
+                );
+                names.push(&principal.name);
+
+                v.check(
+                    !principal.cert_names.is_empty() || !principal.api_keys.is_empty(),
+                    &field,
+                    "needs cert_names or api_keys to be identified by",
+                );
+                v.check(
+                    principal.cert_names.is_empty() || self.server.tls.mode == TlsMode::Mtls,
+                    &field,
+                    "cert_names need server.tls.mode mtls, clients don't send certificates otherwise",
+                );
+                v.check(
+                    principal.api_keys.iter().all(|key| !key.is_empty()),
+                    &field,
+                    "api keys can't be empty",
+                );
+                v.check(
+                    !principal.operations.is_empty(),
+                    &field,
+                    "needs at least one operation",
+                );
+            }
+        }
+    }
+}
+
+/// Config path from `--config <path>`, then `HORBO_CONFIG`, then `horbo.yml`.
+pub fn config_path() -> String {
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            if let Some(path) = args.next() {
+                return path;
+            }
+        } else if let Some(path) = arg.strip_prefix("--config=") {
+            return path.to_string();
+        }
+    }
+
+    env::var("HORBO_CONFIG").unwrap_or_else(|_| DEFAULT_CONFIG_PATH.to_string())
+}
+
+/// Applies `HORBO__` environment variables on top of the parsed document and
+/// returns the dotted paths they replaced. Values are read as YAML scalars,
+/// unless the file had a string there already.
+fn apply_env_overrides(root: &mut serde_yaml::Value) -> Vec<String> {
+    let mut overridden: Vec<String> = Vec::new();
+
+    let mut vars: Vec<(String, String)> = env::vars()
+        .filter(|(key, _)| key.starts_with(ENV_OVERRIDE_PREFIX))
+        .collect();
+    vars.sort();
+
+    for (key, raw) in vars {
+        let path: Vec<String> = key[ENV_OVERRIDE_PREFIX.len()..]
+            .split("__")
+            .map(|segment| segment.to_lowercase())
+            .collect();
+        if path.iter().any(|segment| segment.is_empty()) {
+            continue;
+        }
+
+        set_path(root, &path, raw);
+        overridden.push(path.join("."));
+    }
+
+    overridden
+}
+
+fn set_path(node: &mut serde_yaml::Value, path: &[String], raw: String) {
+    match path.split_first() {
+        Some((segment, rest)) => {
+            if !node.is_mapping() {
+                *node = serde_yaml::Value::Mapping(serde_yaml::Mapping::new());
+            }
+            if let serde_yaml::Value::Mapping(mapping) = node {
+                let child = mapping
+                    .entry(serde_yaml::Value::String(segment.clone()))
+                    .or_insert(serde_yaml::Value::Null);
+                set_path(child, rest, raw);
+            }
+        }
+        None => {
+            *node = match node {
+                serde_yaml::Value::String(_) => serde_yaml::Value::String(raw),
+                _ => serde_yaml::from_str(&raw).unwrap_or(serde_yaml::Value::String(raw)),
+            };
+        }
+    }
+}
+
+/// Dotted path of a setting serde had no field for, e.g. `auth.principals[0].nme`.
+fn field_of(path: &serde_ignored::Path) -> String {
+    use serde_ignored::Path;
+
+    match path {
+        Path::Root => String::new(),
+        Path::Seq { parent, index } => format!("{}[{}]", field_of(parent), index),
+        Path::Map { parent, key } => match field_of(parent) {
+            parent if parent.is_empty() => key.clone(),
+            parent => format!("{}.{}", parent, key),
+        },
+        Path::Some { parent }
+        | Path::NewtypeStruct { parent }
+        | Path::NewtypeVariant { parent } => field_of(parent),
+    }
+}
+
+fn load_services_definition(filepath: &str) -> Result<ServiceDefinition, ConfigError> {
+    let contents =
+        fs::read_to_string(filepath).map_err(|e| ConfigError::Io(filepath.to_string(), e))?;
+    let mut root: serde_yaml::Value =
+        serde_yaml::from_str(&contents).map_err(|e| ConfigError::Parse(filepath.to_string(), e))?;
+
+    /* Parsing the text again keeps line numbers in type errors */
+    let overridden = apply_env_overrides(&mut root);
+    let mut unknown = Vec::new();
+    let on_unknown = |path: serde_ignored::Path| unknown.push(field_of(&path));
+    let definition: ServiceDefinition = match overridden.is_empty() {
+        true => {
+            serde_ignored::deserialize(serde_yaml::Deserializer::from_str(&contents), on_unknown)
+        }
+        false => serde_ignored::deserialize(root, on_unknown),
+    }
+    .map_err(|e| ConfigError::Parse(filepath.to_string(), e))?;
+
+    let mut validator = Validator::new(&contents, &overridden);
+    for field in &unknown {
+        validator.report(field, "isn't a known setting".to_string());
+    }
+    definition.validate(&mut validator);
+    let problems: Vec<ConfigProblem> = validator.into_problems();
+    if !problems.is_empty() {
+        return Err(ConfigError::Invalid(filepath.to_string(), problems));
+    }
+
+    Ok(definition)
+}
+
+pub fn init() -> Result<ServiceDefinition, ConfigError> {
+    let filepath = config_path();
+    load_services_definition(&filepath)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_node_lists_still_parse() {
+        let definition: ServiceDefinition = serde_yaml::from_str(
+            "services:\n  payment: [\"10.0.0.1:5000\", \"10.0.0.2:5000\"]\n  booking: []\n",
+        )
+        .unwrap();
+
+        let payment = &definition.services["payment"];
+        assert_eq!(payment.nodes, vec!["10.0.0.1:5000", "10.0.0.2:5000"]);
+        assert_eq!(payment.virtual_nodes, DEFAULT_VIRTUAL_NODES);
+        assert!(definition.services["booking"].nodes.is_empty());
+    }
+
+    #[test]
+    fn namespace_definitions_parse() {
+        let definition: ServiceDefinition = serde_yaml::from_str(
+            "services:\n  payment:\n    virtual_nodes: 64\n    nodes: [\"10.0.0.1:5000\"]\n",
+        )
+        .unwrap();
+
+        let payment = &definition.services["payment"];
+        assert_eq!(payment.nodes, vec!["10.0.0.1:5000"]);
+        assert_eq!(payment.virtual_nodes, 64);
+    }
+
+    fn value(yaml: &str) -> serde_yaml::Value {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    fn path(dotted: &str) -> Vec<String> {
+        dotted.split('.').map(str::to_string).collect()
+    }
+
+    #[test]
+    fn set_path_creates_missing_parents() {
+        let mut root = value("server:\n  listen: \"[::1]:50051\"\n");
+        set_path(
+            &mut root,
+            &path("services.payment.virtual_nodes"),
+            "32".to_string(),
+        );
+
+        assert_eq!(root["services"]["payment"]["virtual_nodes"], value("32"));
+        assert_eq!(root["server"]["listen"], value("\"[::1]:50051\""));
+    }
+
+    #[test]
+    fn set_path_keeps_string_settings_strings() {
+        let mut root = value("metrics:\n  dest_port: \"34254\"\n  protocol_version: 1\n");
+        set_path(&mut root, &path("metrics.dest_port"), "34255".to_string());
+        set_path(
+            &mut root,
+            &path("metrics.protocol_version"),
+            "2".to_string(),
+        );
+
+        assert_eq!(root["metrics"]["dest_port"], value("\"34255\""));
+        assert_eq!(root["metrics"]["protocol_version"], value("2"));
+    }
+
+    #[test]
+    fn set_path_falls_back_to_a_string_for_unparsable_scalars() {
+        let mut root = value("server:\n  reap_interval_secs: 1\n");
+        set_path(
+            &mut root,
+            &path("server.reap_interval_secs"),
+            "[oops".to_string(),
+        );
+        set_path(
+            &mut root,
+            &path("server.listen"),
+            "0.0.0.0:50051".to_string(),
+        );
+
+        assert_eq!(root["server"]["reap_interval_secs"], value("\"[oops\""));
+        assert_eq!(root["server"]["listen"], value("\"0.0.0.0:50051\""));
+    }
+
+    #[test]
+    fn env_overrides_replace_settings() {
+        /* The only test touching HORBO__ variables, others would see them otherwise */
+        env::set_var("HORBO__SERVICES__PAYMENT__VIRTUAL_NODES", "32");
+        env::set_var("HORBO__METRICS__DEST_PORT", "34255");
+        env::set_var("HORBO__METRICS____KEY", "ignored");
+        let mut root = value(
+            "services:\n  payment:\n    virtual_nodes: 64\nmetrics:\n  dest_port: \"34254\"\n",
+        );
+        let overridden = apply_env_overrides(&mut root);
+        env::remove_var("HORBO__SERVICES__PAYMENT__VIRTUAL_NODES");
+        env::remove_var("HORBO__METRICS__DEST_PORT");
+        env::remove_var("HORBO__METRICS____KEY");
+
+        assert_eq!(
+            overridden,
+            vec!["metrics.dest_port", "services.payment.virtual_nodes"]
+        );
+        assert_eq!(root["services"]["payment"]["virtual_nodes"], value("32"));
+        assert_eq!(root["metrics"]["dest_port"], value("\"34255\""));
+    }
+
+    #[test]
+    fn refuses_the_sample_metrics_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("horbo.yml");
+        fs::write(
+            &path,
+            "services: {}\nmetrics:\n  dest_port: \"34254\"\n  start_flag: \"FF\"\n  key: change-me\n  message_length:\n    reserved_bytes: 2\n  protocol_version: 1\n  message_body:\n    cpu:\n      id: 1\n    memory:\n      id: 2\n    namespace:\n      id: 3\n    service_id:\n      id: 4\n    key:\n      id: 5\n",
+        )
+        .unwrap();
+
+        match load_services_definition(path.to_str().unwrap()) {
+            Err(ConfigError::Invalid(_, problems)) => {
+                let problem = problems
+                    .iter()
+                    .find(|problem| problem.field == "metrics.key")
+                    .expect("no problem with metrics.key");
+                assert_eq!(problem.line, Some(5));
+            }
+            other => panic!("sample key was accepted: {:?}", other.map(|_| ())),
+        }
+    }
+}
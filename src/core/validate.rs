@@ -0,0 +1,191 @@
+use crate::common::error::ConfigProblem;
+
+/// Collects every problem of a configuration instead of stopping at the first,
+/// pointing each one at its line in the file it was loaded from.
+pub struct Validator<'a> {
+    contents: &'a str,
+    /// Settings replaced by environment variables, their line would be misleading.
+    overridden: &'a [String],
+    problems: Vec<ConfigProblem>,
+}
+
+impl<'a> Validator<'a> {
+    pub fn new(contents: &'a str, overridden: &'a [String]) -> Self {
+        Validator {
+            contents,
+            overridden,
+            problems: Vec::new(),
+        }
+    }
+
+    /// Records a problem with `field` unless `ok` holds.
+    pub fn check(&mut self, ok: bool, field: &str, message: &str) {
+        if !ok {
+            self.report(field, message.to_string());
+        }
+    }
+
+    pub fn report(&mut self, field: &str, message: String) {
+        let overridden = self
+            .overridden
+            .iter()
+            .any(|path| field == path || field.starts_with(&format!("{}.", path)));
+
+        self.problems.push(ConfigProblem {
+            field: field.to_string(),
+            line: match overridden {
+                true => None,
+                false => line_of(self.contents, field),
+            },
+            message,
+        });
+    }
+
+    pub fn into_problems(self) -> Vec<ConfigProblem> {
+        self.problems
+    }
+}
+
+/// 1-based line of a dotted field path in a block style YAML document, e.g.
+/// `services.payment.nodes[1]` or `auth.principals[0].name`. Falls back to the
+/// closest parent found, since settings left to their default don't appear in
+/// the file.
+fn line_of(contents: &str, field: &str) -> Option<usize> {
+    let mut lines: Vec<String> = contents.lines().map(str::to_string).collect();
+    let indent_of = |line: &str| line.len() - line.trim_start().len();
+    let is_content = |line: &str| {
+        let trimmed = line.trim_start();
+        !trimmed.is_empty() && !trimmed.starts_with('#')
+    };
+
+    let mut found: Option<usize> = None;
+    let mut start = 0;
+    let mut parent_indent: Option<usize> = None;
+
+    for segment in field.split('.') {
+        let (key, index) = match segment.split_once('[') {
+            Some((key, rest)) => (key, rest.trim_end_matches(']').parse::<usize>().ok()),
+            None => (segment, None),
+        };
+
+        /* Keys of a block all sit at the indentation of its first line */
+        let mut child_indent: Option<usize> = None;
+        let mut hit: Option<(usize, usize)> = None;
+        for (i, line) in lines.iter().enumerate().skip(start) {
+            if !is_content(line) {
+                continue;
+            }
+            let indent = indent_of(line);
+            if parent_indent.is_some_and(|parent| indent <= parent) {
+                break;
+            }
+            if *child_indent.get_or_insert(indent) != indent {
+                continue;
+            }
+            if line.trim_start().starts_with(&format!("{}:", key)) {
+                hit = Some((i, indent));
+                break;
+            }
+        }
+
+        let (i, indent) = match hit {
+            Some(hit) => hit,
+            None => break,
+        };
+        found = Some(i);
+        start = i + 1;
+        parent_indent = Some(indent);
+
+        /* Sequence items may sit at the same indentation as their key */
+        if let Some(index) = index {
+            let items = lines
+                .iter()
+                .enumerate()
+                .skip(start)
+                .filter(|(_, line)| is_content(line))
+                .take_while(|(_, line)| {
+                    indent_of(line) > indent || line.trim_start().starts_with('-')
+                })
+                .filter(|(_, line)| line.trim_start().starts_with('-'));
+            let j = match items.into_iter().nth(index) {
+                Some((j, _)) => j,
+                None => break,
+            };
+            found = Some(j);
+
+            /* Without its dash the item reads as a block of keys like any other */
+            let dash = indent_of(&lines[j]);
+            lines[j].replace_range(dash..dash + 1, " ");
+            start = j;
+            parent_indent = Some(dash);
+        }
+    }
+
+    found.map(|i| i + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "\
+server:
+  listen: \"[::1]:50051\"
+services:
+  # payment first
+  payment:
+    virtual_nodes: 64
+    nodes:
+      - 10.0.0.1:5000
+      - 10.0.0.2:5000
+  booking:
+    virtual_nodes: 32
+auth:
+  principals:
+    - name: agent
+      api_keys: [\"ka\"]
+    - name: gateway
+      api_keys: [\"kb\"]
+";
+
+    #[test]
+    fn finds_nested_keys() {
+        assert_eq!(line_of(CONFIG, "server.listen"), Some(2));
+        assert_eq!(line_of(CONFIG, "services.payment.virtual_nodes"), Some(6));
+        assert_eq!(line_of(CONFIG, "services.booking.virtual_nodes"), Some(11));
+    }
+
+    #[test]
+    fn finds_sequence_items_and_their_keys() {
+        assert_eq!(line_of(CONFIG, "services.payment.nodes[1]"), Some(9));
+        assert_eq!(line_of(CONFIG, "auth.principals[1]"), Some(16));
+        assert_eq!(line_of(CONFIG, "auth.principals[0].api_keys"), Some(15));
+        assert_eq!(line_of(CONFIG, "auth.principals[1].api_keys"), Some(17));
+    }
+
+    #[test]
+    fn falls_back_to_the_nearest_parent() {
+        assert_eq!(
+            line_of(CONFIG, "services.payment.health.cpu_limit"),
+            Some(5)
+        );
+        assert_eq!(line_of(CONFIG, "services.payment.nodes[5]"), Some(7));
+        assert_eq!(line_of(CONFIG, "auth.principals[0].namespaces"), Some(14));
+        assert_eq!(line_of(CONFIG, "storage.dir"), None);
+    }
+
+    #[test]
+    fn leaves_overridden_settings_without_a_line() {
+        let overridden = vec!["services.payment".to_string()];
+        let mut validator = Validator::new(CONFIG, &overridden);
+        validator.report("services.payment.virtual_nodes", "too many".to_string());
+        validator.report("services.booking.virtual_nodes", "too many".to_string());
+
+        let lines: Vec<Option<usize>> = validator
+            .into_problems()
+            .iter()
+            .map(|problem| problem.line)
+            .collect();
+        assert_eq!(lines, vec![None, Some(11)]);
+    }
+}
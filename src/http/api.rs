@@ -0,0 +1,363 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+
+use actix_web::dev::Server;
+use actix_web::http::StatusCode;
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer, ResponseError};
+use serde::Deserialize;
+use serde_json::json;
+use tonic::{Code, Status};
+
+use crate::auth::interceptor::API_KEY_HEADER;
+use crate::auth::policy::{AuthPolicy, Caller, Operation};
+use crate::common::error::ErrorResponse;
+use crate::core::{
+    application::service_discovery::ServiceDiscovery,
+    domain::server::ServiceDiscoveryUsecase,
+    schema::{HttpDefinition, NamespaceDefinition},
+};
+use crate::grpc::HeartbeatResponse;
+use crate::telemetry::registry;
+
+/// Shared by every worker of the HTTP server.
+pub struct ApiState {
+    pub service: Arc<ServiceDiscovery>,
+    pub auth: Arc<AuthPolicy>,
+}
+
+/// A failed call, answered as `{"error": "..."}`.
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        ApiError {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status).json(json!({ "error": self.message }))
+    }
+}
+
+/// Same split as gRPC, unknown or invalid namespaces are the caller's fault.
+impl From<ErrorResponse> for ApiError {
+    fn from(e: ErrorResponse) -> Self {
+        match e {
+            ErrorResponse::BadRequest(message) => ApiError::new(StatusCode::BAD_REQUEST, message),
+            ErrorResponse::Unavailable(message) => {
+                ApiError::new(StatusCode::SERVICE_UNAVAILABLE, message)
+            }
+            ErrorResponse::Forbidden(message) => ApiError::new(StatusCode::FORBIDDEN, message),
+            e => ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        }
+    }
+}
+
+/// Auth failures come as gRPC statuses.
+impl From<Status> for ApiError {
+    fn from(status: Status) -> Self {
+        let code = match status.code() {
+            Code::Unauthenticated => StatusCode::UNAUTHORIZED,
+            Code::PermissionDenied => StatusCode::FORBIDDEN,
+            Code::InvalidArgument => StatusCode::BAD_REQUEST,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        ApiError::new(code, status.message())
+    }
+}
+
+type ApiResult = Result<HttpResponse, ApiError>;
+
+/// Same rules as gRPC calls, except callers can only be identified by api key
+/// since the API is served in plaintext. Returns who the caller is.
+fn authorize(
+    state: &ApiState,
+    request: &HttpRequest,
+    namespace: Option<&str>,
+    operation: Operation,
+) -> Result<Caller, ApiError> {
+    if !state.auth.enabled() {
+        return Ok(Caller::default());
+    }
+
+    let api_key = match request.headers().get(API_KEY_HEADER) {
+        Some(value) => match value.to_str() {
+            Ok(key) => Some(key),
+            Err(_) => {
+                return Err(ApiError::new(
+                    StatusCode::UNAUTHORIZED,
+                    "api key isn't valid ascii",
+                ))
+            }
+        },
+        None => None,
+    };
+
+    let caller = state.auth.identify(None, api_key)?;
+    state.auth.authorize(&caller, namespace, operation)?;
+
+    Ok(caller)
+}
+
+/// Parses a `key=value,key=value` selector, no selector matches every node.
+fn parse_selector(raw: Option<&str>) -> Result<HashMap<String, String>, ApiError> {
+    let mut selector = HashMap::new();
+    let raw = match raw {
+        Some(raw) if !raw.trim().is_empty() => raw,
+        _ => return Ok(selector),
+    };
+
+    for label in raw.split(',') {
+        match label.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => {
+                selector.insert(key.trim().to_string(), value.trim().to_string());
+            }
+            _ => {
+                return Err(ApiError::new(
+                    StatusCode::BAD_REQUEST,
+                    format!("selector label {:?} isn't key=value", label),
+                ))
+            }
+        }
+    }
+
+    Ok(selector)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InstancesQuery {
+    /// `healthy`, `unhealthy` or `any`.
+    health: Option<String>,
+    selector: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LookupQuery {
+    /// Address hashed onto the ring, the caller's address when left out.
+    client: Option<String>,
+    selector: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateNamespaceBody {
+    namespace: String,
+    /// Same fields as a `services:` entry of `horbo.yml`.
+    #[serde(default)]
+    settings: NamespaceDefinition,
+}
+
+/// Prometheus scrape target, left unauthenticated like most exporters.
+async fn metrics(state: web::Data<ApiState>) -> ApiResult {
+    let body = registry::render(&state.service)?;
+    Ok(HttpResponse::Ok()
+        .content_type(prometheus::TEXT_FORMAT)
+        .body(body))
+}
+
+/// Nodes currently considered unhealthy, grouped by namespace, in the namespaces
+/// the caller may look up.
+async fn health(state: web::Data<ApiState>, request: HttpRequest) -> ApiResult {
+    let caller = authorize(&state, &request, None, Operation::Lookup)?;
+
+    let mut unhealthy_services = state.service.unhealthy_services().await?;
+    unhealthy_services.retain(|nodes| {
+        state
+            .auth
+            .allows(&caller, &nodes.namespace, Operation::Lookup)
+    });
+    Ok(HttpResponse::Ok().json(HeartbeatResponse { unhealthy_services }))
+}
+
+/// Namespaces the caller may administer.
+async fn list_namespaces(state: web::Data<ApiState>, request: HttpRequest) -> ApiResult {
+    let caller = authorize(&state, &request, None, Operation::Admin)?;
+
+    let mut namespaces = state.service.list_namespaces().await?;
+    namespaces.namespaces.retain(|namespace| {
+        state
+            .auth
+            .allows(&caller, &namespace.namespace, Operation::Admin)
+    });
+    Ok(HttpResponse::Ok().json(namespaces))
+}
+
+async fn describe_namespace(
+    state: web::Data<ApiState>,
+    request: HttpRequest,
+    namespace: web::Path<String>,
+) -> ApiResult {
+    let namespace = namespace.into_inner();
+    authorize(&state, &request, Some(&namespace), Operation::Admin)?;
+
+    let description = state.service.describe_namespace(namespace).await?;
+    Ok(HttpResponse::Ok().json(description))
+}
+
+async fn list_instances(
+    state: web::Data<ApiState>,
+    request: HttpRequest,
+    namespace: web::Path<String>,
+    query: web::Query<InstancesQuery>,
+) -> ApiResult {
+    let namespace = namespace.into_inner();
+    authorize(&state, &request, Some(&namespace), Operation::Lookup)?;
+
+    let healthy = match query.health.as_deref() {
+        None | Some("any") => None,
+        Some("healthy") => Some(true),
+        Some("unhealthy") => Some(false),
+        Some(other) => {
+            return Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                format!("health {:?} isn't one of healthy, unhealthy or any", other),
+            ))
+        }
+    };
+    let labels = parse_selector(query.selector.as_deref())?;
+
+    let instances = state
+        .service
+        .list_instances(namespace, healthy, labels)
+        .await?;
+    Ok(HttpResponse::Ok().json(instances))
+}
+
+async fn lookup(
+    state: web::Data<ApiState>,
+    request: HttpRequest,
+    namespace: web::Path<String>,
+    query: web::Query<LookupQuery>,
+) -> ApiResult {
+    let namespace = namespace.into_inner();
+    authorize(&state, &request, Some(&namespace), Operation::Lookup)?;
+
+    /* Hash on the client host only, like gRPC lookups */
+    let client = match (&query.client, request.peer_addr()) {
+        (Some(client), _) => client.clone(),
+        (None, Some(peer)) => peer.ip().to_string(),
+        (None, None) => {
+            return Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                "client ip is not valid",
+            ))
+        }
+    };
+    let selector = parse_selector(query.selector.as_deref())?;
+
+    let node = state
+        .service
+        .service_lookup(namespace, client, selector)
+        .await?;
+    Ok(HttpResponse::Ok().json(node))
+}
+
+async fn create_namespace(
+    state: web::Data<ApiState>,
+    request: HttpRequest,
+    body: web::Json<CreateNamespaceBody>,
+) -> ApiResult {
+    let body = body.into_inner();
+    authorize(&state, &request, Some(&body.namespace), Operation::Admin)?;
+
+    let description = state
+        .service
+        .create_namespace(body.namespace, body.settings)
+        .await?;
+    Ok(HttpResponse::Created().json(description))
+}
+
+async fn delete_namespace(
+    state: web::Data<ApiState>,
+    request: HttpRequest,
+    namespace: web::Path<String>,
+) -> ApiResult {
+    let namespace = namespace.into_inner();
+    authorize(&state, &request, Some(&namespace), Operation::Admin)?;
+
+    state.service.delete_namespace(namespace).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+async fn deregister_instance(
+    state: web::Data<ApiState>,
+    request: HttpRequest,
+    path: web::Path<(String, String)>,
+) -> ApiResult {
+    let (namespace, service_id) = path.into_inner();
+    authorize(&state, &request, Some(&namespace), Operation::Admin)?;
+
+    /* Admins may remove any node, not only the ones they registered */
+    state
+        .service
+        .deregister_node(namespace, service_id, None)
+        .await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Read-only endpoints, plus the ones changing the registry with `admin`.
+fn routes(cfg: &mut web::ServiceConfig, admin: bool) {
+    cfg.route("/metrics", web::get().to(metrics))
+        .route("/health", web::get().to(health))
+        .route("/namespaces", web::get().to(list_namespaces))
+        .route("/namespaces/{namespace}", web::get().to(describe_namespace))
+        .route(
+            "/namespaces/{namespace}/instances",
+            web::get().to(list_instances),
+        )
+        .route("/namespaces/{namespace}/lookup", web::get().to(lookup));
+
+    if admin {
+        cfg.route("/namespaces", web::post().to(create_namespace))
+            .route(
+                "/namespaces/{namespace}",
+                web::delete().to(delete_namespace),
+            )
+            .route(
+                "/namespaces/{namespace}/instances/{service_id}",
+                web::delete().to(deregister_instance),
+            );
+    }
+}
+
+/// Binds the HTTP API, requests are only served once the returned server is
+/// awaited or spawned.
+pub fn serve(
+    definition: &HttpDefinition,
+    service: Arc<ServiceDiscovery>,
+    auth: Arc<AuthPolicy>,
+) -> io::Result<Server> {
+    let admin = definition.admin;
+    let state = web::Data::new(ApiState { service, auth });
+
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(state.clone())
+            .configure(|cfg| routes(cfg, admin))
+    })
+    /* Signals are the process' business, not only the HTTP server's */
+    .disable_signals()
+    .bind(definition.listen_addr())?
+    .run();
+
+    Ok(server)
+}
@@ -0,0 +1,13 @@
+pub mod auth;
+pub mod common;
+pub mod core;
+#[allow(dead_code)]
+pub mod grpc;
+pub mod http;
+pub mod metrics;
+pub mod pool;
+pub mod server;
+pub mod storage;
+pub mod telemetry;
+pub mod tls;
+pub mod utils;
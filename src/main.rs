@@ -1,18 +1,24 @@
-use crate::grpc::horbo_server::HorboServer;
-use crate::pool::consistent_hash::{build, Ring};
-use crate::server::HorboServiceController;
-use core::schema::{init, ServiceDefinition};
 use std::collections::HashMap;
-use std::fs;
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use tonic::transport::{Certificate, Identity, Server as TonicServer, ServerTlsConfig};
-mod common;
-mod core;
-mod grpc;
-mod pool;
-mod server;
-mod utils;
+use std::time::Duration;
+use tokio::net::{TcpListener, UdpSocket};
+use tokio::sync::Notify;
+use tonic::transport::Server as TonicServer;
+use tonic_health::ServingStatus;
+use ulok::auth::interceptor::authenticate;
+use ulok::auth::policy::AuthPolicy;
+use ulok::core::application::service_discovery::ServiceDiscovery;
+use ulok::core::application::{reaper, shutdown, snapshotter};
+use ulok::core::domain::server::ServiceDiscoveryUsecase;
+use ulok::core::schema::{init, ServiceDefinition, TlsMode};
+use ulok::grpc::horbo_server::HorboServer;
+use ulok::http;
+use ulok::metrics;
+use ulok::pool::consistent_hash::{build, Ring};
+use ulok::server::{HorboServiceController, FILE_DESCRIPTOR_SET};
+use ulok::storage::file::FileStorage;
+use ulok::telemetry::logging;
+use ulok::tls::{self, ReloadableTls};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -20,38 +26,157 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let services_definition: ServiceDefinition = match init() {
         Ok(def) => def,
         Err(e) => {
-            panic!("{}", e)
+            eprintln!("{}", e);
+            std::process::exit(1);
         }
     };
+    logging::init(&services_definition.logging);
 
     /* init `services` singleton */
     let mut services: HashMap<String, Ring> = HashMap::new();
-    for (name, ip_addres) in services_definition.services.into_iter() {
-        services.insert(name.clone(), build(name, ip_addres));
+    for (name, definition) in services_definition.services.into_iter() {
+        services.insert(name.clone(), build(name, definition));
+    }
+
+    let mut discovery = ServiceDiscovery::new(services);
+
+    /* replay persisted registry state before serving */
+    let mut snapshot_every = None;
+    if let Some(storage) = services_definition.storage {
+        discovery = discovery.with_storage(Arc::new(FileStorage::open(&storage.dir)?));
+        let replayed = discovery.recover()?;
+        tracing::info!(records = replayed, dir = %storage.dir, "recovered registry state");
+        snapshot_every = Some(Duration::from_secs(storage.snapshot_every_secs.max(1)));
+    }
+
+    let service = Arc::new(discovery);
+
+    /* Nothing listens before the registry is recovered, so probes answered are ready */
+    let (health, health_service) = tonic_health::server::health_reporter();
+    health
+        .set_serving::<HorboServer<HorboServiceController>>()
+        .await;
+
+    /* compact the log, the first snapshot is taken right away */
+    if let Some(every) = snapshot_every {
+        tokio::spawn(snapshotter::run(service.clone(), every));
+    }
+
+    /* evict nodes that stopped sending heartbeats */
+    let reap_every = Duration::from_secs(services_definition.server.reap_interval_secs);
+    tokio::spawn(reaper::run(service.clone(), reap_every));
+
+    let listen = services_definition.server.listen_addr();
+
+    /* lightweight heartbeats over the framed UDP metrics protocol */
+    if let Some(metrics) = services_definition.metrics {
+        for setting in metrics.unused() {
+            tracing::warn!(
+                setting,
+                "ignoring setting, it can be removed from the config"
+            );
+        }
+        let protocol = metrics.protocol();
+        let socket = UdpSocket::bind((listen.ip(), protocol.port)).await?;
+        tokio::spawn(metrics::listener::run(service.clone(), socket, protocol));
     }
 
     /* build and serve grpc */
-    let svc = HorboServer::new(HorboServiceController {
-        service: Arc::new(Mutex::new(
-            core::application::service_discovery::ServiceDiscovery::new(services),
-        )),
-    });
-
-    /* mTLS support */
-    let server_cert = fs::read("./keys/server.crt")?;
-    let server_key = fs::read("./keys/server.key")?;
-    let server_identity = Identity::from_pem(server_cert, server_key);
-
-    let client_ca_cert = fs::read("./keys/ca.crt")?;
-    let client_ca = Certificate::from_pem(client_ca_cert);
-    let tls_config = ServerTlsConfig::new()
-        .identity(server_identity)
-        .client_ca_root(client_ca);
-
-    TonicServer::builder()
-        .tls_config(tls_config)?
-        .add_service(svc)
-        .serve("[::1]:50051".parse()?)
-        .await?;
+    let auth = Arc::new(AuthPolicy::new(services_definition.auth.as_ref()));
+    if !auth.enabled() {
+        tracing::warn!("no auth configured, any client may call any operation on any namespace");
+    }
+
+    /* JSON API next to grpc, read-only unless admin endpoints are enabled */
+    let mut http_server = None;
+    if let Some(http) = &services_definition.http {
+        let server = http::api::serve(http, service.clone(), auth.clone())?;
+        http_server = Some(server.handle());
+        tokio::spawn(server);
+    }
+
+    let svc = HorboServer::with_interceptor(
+        HorboServiceController {
+            service: service.clone(),
+            auth: auth.clone(),
+        },
+        authenticate(auth),
+    );
+
+    /* mTLS unless configured otherwise, certificates are reloaded when they change on disk */
+    let tls = &services_definition.server.tls;
+    let reflection = || {
+        tonic_reflection::server::Builder::configure()
+            .register_encoded_file_descriptor_set(FILE_DESCRIPTOR_SET)
+            .register_encoded_file_descriptor_set(tonic_health::pb::FILE_DESCRIPTOR_SET)
+    };
+    let router = TonicServer::builder()
+        .add_service(health_service)
+        /* v1alpha for tools predating the stable reflection API */
+        .add_service(reflection().build_v1()?)
+        .add_service(reflection().build_v1alpha()?)
+        .add_service(svc);
+
+    /* new connections are refused as soon as shutdown starts, calls in flight may finish */
+    let stopping = Arc::new(Notify::new());
+    let stopped = {
+        let stopping = stopping.clone();
+        async move { stopping.notified().await }
+    };
+    let mut serving = match tls.mode {
+        TlsMode::Plaintext => {
+            tracing::warn!(
+                "serving gRPC in plaintext, every call is unencrypted and unauthenticated"
+            );
+            tokio::spawn(router.serve_with_shutdown(listen, stopped))
+        }
+        _ => {
+            let tls = Arc::new(ReloadableTls::load(tls)?);
+            tokio::spawn(tls::watch(tls.clone(), tls::RELOAD_INTERVAL));
+
+            let listener = TcpListener::bind(listen).await?;
+            tokio::spawn(router.serve_with_incoming_shutdown(tls::incoming(listener, tls), stopped))
+        }
+    };
+
+    tokio::select! {
+        served = &mut serving => {
+            /* Only ends on its own when it fails */
+            served??;
+            return Ok(());
+        }
+        _ = shutdown::signal() => {}
+    }
+
+    let grace = Duration::from_secs(services_definition.server.shutdown_grace_secs);
+    tracing::info!(grace_secs = grace.as_secs(), "shutting down");
+
+    /* probes and load balancers stop routing here first */
+    health
+        .set_not_serving::<HorboServer<HorboServiceController>>()
+        .await;
+    health
+        .set_service_status("", ServingStatus::NotServing)
+        .await;
+    service.shut_down();
+    stopping.notify_one();
+
+    let drained = async {
+        if let Some(http) = &http_server {
+            http.stop(true).await;
+        }
+        serving.await
+    };
+    match tokio::time::timeout(grace, drained).await {
+        Ok(served) => served??,
+        Err(_) => tracing::warn!("grace period elapsed, dropping calls still in flight"),
+    }
+
+    /* flush: the log is folded into a snapshot, nothing is left to replay on start */
+    if let Err(e) = service.checkpoint().await {
+        tracing::error!(error = %e, "failed to snapshot registry on shutdown");
+    }
+    tracing::info!("stopped");
+
     Ok(())
 }
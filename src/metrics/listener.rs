@@ -0,0 +1,50 @@
+use std::sync::Arc;
+
+use tokio::net::UdpSocket;
+
+use crate::common::error::ErrorResponse;
+use crate::core::{
+    application::service_discovery::ServiceDiscovery, domain::server::ServiceDiscoveryUsecase,
+};
+use crate::metrics::protocol::MetricsProtocol;
+
+/// Largest payload a UDP datagram can carry.
+const MAX_DATAGRAM: usize = 65_507;
+
+/// Receives metrics frames and feeds them through the same health logic as the
+/// gRPC `Heartbeat`. Nothing is sent back, agents that want the unhealthy node
+/// list still need to heartbeat over gRPC. Frames prove the shared key rather
+/// than who sent them, so they're taken for any node. Runs until the runtime stops.
+pub async fn run(service: Arc<ServiceDiscovery>, socket: UdpSocket, protocol: MetricsProtocol) {
+    let mut buf = vec![0u8; MAX_DATAGRAM];
+
+    loop {
+        let (len, peer) = match socket.recv_from(&mut buf).await {
+            Ok(received) => received,
+            Err(e) => {
+                tracing::warn!(error = %e, "failed to receive metrics datagram");
+                continue;
+            }
+        };
+
+        let frame = match protocol.decode(&buf[..len]) {
+            Ok(frame) => frame,
+            Err(e) => {
+                tracing::warn!(%peer, error = %e, "dropping metrics datagram");
+                continue;
+            }
+        };
+
+        /* Unknown namespaces and service ids are the agent's mistake, not ours */
+        match service
+            .node_heartbeat(frame.namespace, frame.service_id, frame.metric, None)
+            .await
+        {
+            Ok(_) => {}
+            Err(e @ ErrorResponse::BadRequest(_)) => {
+                tracing::warn!(%peer, error = %e, "ignoring metrics");
+            }
+            Err(e) => tracing::error!(%peer, error = %e, "failed to apply metrics"),
+        }
+    }
+}
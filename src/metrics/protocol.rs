@@ -0,0 +1,258 @@
+use crate::auth::policy::constant_time_eq;
+use crate::common::error::ErrorResponse;
+use crate::core::domain::data::UtilizationMetric;
+
+/// Parameters of the framed UDP metrics protocol, built from the `metrics:`
+/// block of `horbo.yml`.
+///
+/// A datagram carries exactly one frame:
+///
+/// ```text
+/// | start_flag (1) | length (length_bytes, big endian) | version (1) | field ... |
+/// ```
+///
+/// `length` counts every byte after the length field itself. Each field is
+///
+/// ```text
+/// | id (1) | size (1) | value (size) |
+/// ```
+///
+/// where cpu and memory are big endian `f32` percentages and namespace and
+/// service id are UTF-8. A `key` field carries the shared secret of the
+/// `metrics:` block, frames without it are rejected since anyone reaching the
+/// port could flip nodes unhealthy otherwise. All five are required, fields
+/// with unknown ids are skipped so agents can send more than this version
+/// understands.
+#[derive(Debug, Clone)]
+pub struct MetricsProtocol {
+    /// Port metrics datagrams are received on.
+    pub port: u16,
+    pub start_flag: u8,
+    pub length_bytes: usize,
+    pub version: u8,
+    pub cpu_id: u8,
+    pub memory_id: u8,
+    pub namespace_id: u8,
+    pub service_id_id: u8,
+    pub key_id: u8,
+    /// Shared secret a frame has to carry.
+    pub key: Vec<u8>,
+}
+
+/// A decoded metrics frame, the UDP equivalent of a `HeartbeatRequest`.
+pub struct MetricsFrame {
+    pub namespace: String,
+    pub service_id: String,
+    pub metric: UtilizationMetric,
+}
+
+fn malformed(reason: &str) -> ErrorResponse {
+    ErrorResponse::BadRequest(format!("malformed metrics frame: {}", reason))
+}
+
+impl MetricsProtocol {
+    pub fn decode(&self, datagram: &[u8]) -> Result<MetricsFrame, ErrorResponse> {
+        let header = 1 + self.length_bytes;
+        if datagram.len() < header + 1 {
+            return Err(malformed("too short"));
+        }
+        if datagram[0] != self.start_flag {
+            return Err(malformed("bad start flag"));
+        }
+
+        let length = datagram[1..header]
+            .iter()
+            .fold(0usize, |acc, byte| (acc << 8) | *byte as usize);
+        if datagram.len() - header != length {
+            return Err(malformed("length doesn't match datagram size"));
+        }
+
+        let body = &datagram[header..];
+        if body[0] != self.version {
+            return Err(ErrorResponse::BadRequest(format!(
+                "unsupported metrics protocol version {}",
+                body[0]
+            )));
+        }
+
+        let mut cpu_usage: Option<f32> = None;
+        let mut memory_usage: Option<f32> = None;
+        let mut namespace: Option<String> = None;
+        let mut service_id: Option<String> = None;
+        let mut key: Option<&[u8]> = None;
+
+        let mut pos = 1;
+        while pos < body.len() {
+            if pos + 2 > body.len() {
+                return Err(malformed("truncated field header"));
+            }
+            let (id, size) = (body[pos], body[pos + 1] as usize);
+            let value = match body.get(pos + 2..pos + 2 + size) {
+                Some(value) => value,
+                None => return Err(malformed("truncated field value")),
+            };
+            pos += 2 + size;
+
+            if id == self.cpu_id {
+                cpu_usage = Some(decode_f32(value)?);
+            } else if id == self.memory_id {
+                memory_usage = Some(decode_f32(value)?);
+            } else if id == self.namespace_id {
+                namespace = Some(decode_str(value)?);
+            } else if id == self.service_id_id {
+                service_id = Some(decode_str(value)?);
+            } else if id == self.key_id {
+                key = Some(value);
+            }
+        }
+
+        if !key.is_some_and(|key| constant_time_eq(key, &self.key)) {
+            return Err(ErrorResponse::BadRequest(
+                "metrics frame without the shared key".to_string(),
+            ));
+        }
+
+        match (namespace, service_id, cpu_usage, memory_usage) {
+            (Some(namespace), Some(service_id), Some(cpu_usage), Some(memory_usage)) => {
+                Ok(MetricsFrame {
+                    namespace,
+                    service_id,
+                    metric: UtilizationMetric {
+                        cpu_usage,
+                        memory_usage,
+                    },
+                })
+            }
+            _ => Err(malformed("missing namespace, service id, cpu or memory")),
+        }
+    }
+}
+
+fn decode_f32(value: &[u8]) -> Result<f32, ErrorResponse> {
+    match <[u8; 4]>::try_from(value) {
+        Ok(bytes) => Ok(f32::from_be_bytes(bytes)),
+        Err(_) => Err(malformed("metric values must be 4 bytes")),
+    }
+}
+
+fn decode_str(value: &[u8]) -> Result<String, ErrorResponse> {
+    match std::str::from_utf8(value) {
+        Ok(s) => Ok(s.to_string()),
+        Err(_) => Err(malformed("text fields must be UTF-8")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn protocol() -> MetricsProtocol {
+        MetricsProtocol {
+            port: 34254,
+            start_flag: 0xFF,
+            length_bytes: 2,
+            version: 1,
+            cpu_id: 1,
+            memory_id: 2,
+            namespace_id: 3,
+            service_id_id: 4,
+            key_id: 5,
+            key: b"secret".to_vec(),
+        }
+    }
+
+    fn field(id: u8, value: &[u8]) -> Vec<u8> {
+        let mut field = vec![id, value.len() as u8];
+        field.extend_from_slice(value);
+        field
+    }
+
+    fn frame(fields: &[Vec<u8>]) -> Vec<u8> {
+        let mut body = vec![1];
+        for field in fields {
+            body.extend_from_slice(field);
+        }
+        let mut frame = vec![0xFF];
+        frame.extend_from_slice(&(body.len() as u16).to_be_bytes());
+        frame.extend(body);
+        frame
+    }
+
+    fn fields() -> Vec<Vec<u8>> {
+        vec![
+            field(1, &42.5f32.to_be_bytes()),
+            field(2, &10.0f32.to_be_bytes()),
+            field(3, b"payment"),
+            field(4, b"01J0000000000000000000000"),
+            field(5, b"secret"),
+        ]
+    }
+
+    fn rejected(datagram: &[u8]) -> String {
+        match protocol().decode(datagram) {
+            Ok(_) => panic!("frame was accepted"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn decodes_a_valid_frame() {
+        let frame = protocol().decode(&frame(&fields())).unwrap();
+
+        assert_eq!(frame.namespace, "payment");
+        assert_eq!(frame.service_id, "01J0000000000000000000000");
+        assert_eq!(frame.metric.cpu_usage, 42.5);
+        assert_eq!(frame.metric.memory_usage, 10.0);
+    }
+
+    #[test]
+    fn rejects_a_wrong_start_flag() {
+        let mut datagram = frame(&fields());
+        datagram[0] = 0xFE;
+
+        assert!(rejected(&datagram).contains("bad start flag"));
+    }
+
+    #[test]
+    fn rejects_a_length_not_matching_the_datagram() {
+        let mut datagram = frame(&fields());
+        datagram.push(0);
+
+        assert!(rejected(&datagram).contains("length doesn't match"));
+    }
+
+    #[test]
+    fn rejects_a_truncated_field() {
+        let mut fields = fields();
+        fields.push(vec![6, 8, 0, 0]);
+
+        assert!(rejected(&frame(&fields)).contains("truncated field value"));
+    }
+
+    #[test]
+    fn skips_unknown_field_ids() {
+        let mut fields = fields();
+        fields.insert(2, field(9, b"from a newer agent"));
+
+        let frame = protocol().decode(&frame(&fields)).unwrap();
+        assert_eq!(frame.namespace, "payment");
+    }
+
+    #[test]
+    fn rejects_a_missing_required_field() {
+        let mut fields = fields();
+        fields.remove(1);
+
+        assert!(rejected(&frame(&fields)).contains("missing namespace, service id, cpu or memory"));
+    }
+
+    #[test]
+    fn rejects_a_missing_or_wrong_key() {
+        let mut fields = fields();
+        fields.pop();
+        assert!(rejected(&frame(&fields)).contains("shared key"));
+
+        fields.push(field(5, b"guess"));
+        assert!(rejected(&frame(&fields)).contains("shared key"));
+    }
+}
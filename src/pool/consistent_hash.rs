@@ -1,206 +1,813 @@
 use crate::common::error::ErrorResponse;
-use crate::core::domain::data::Node;
-use crate::grpc::Node as NodeGrpc;
+use crate::core::application::threshold_policy::ThresholdPolicy;
+use crate::core::domain::data::{Heartbeat, Node, UtilizationMetric};
+use crate::core::domain::failure_report::FailureQuorum;
+use crate::core::domain::health::{HealthPolicy, NodeHealth};
+use crate::core::domain::watch::{Change, MembershipEvent};
+use crate::core::schema::NamespaceDefinition;
 use crate::pool::pool::NodePool;
-use crate::utils::hash::ip_to_hash;
+use crate::utils::addr::{host_of, port_of};
+use crate::utils::hash::{ip_to_hash, vnode_hash};
+use arc_swap::{ArcSwap, Guard};
 use std::collections::HashMap;
-use std::sync::RwLock;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use ulid::Ulid;
+
+pub const DEFAULT_VIRTUAL_NODES: u32 = 1;
+pub const DEFAULT_HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(15);
+pub const DEFAULT_EVICTION_TTL: Duration = Duration::from_secs(60);
+/// Membership events a watcher may fall behind by before it has to start over
+/// from a snapshot.
+pub const WATCH_BUFFER: usize = 256;
+
+/// Per namespace knobs of a ring.
+#[derive(Debug, Clone)]
+pub struct RingSettings {
+    pub virtual_nodes: u32,
+    /// A node that hasn't sent a heartbeat for this long is marked unhealthy.
+    pub heartbeat_timeout: Duration,
+    /// A node that hasn't sent a heartbeat for this long is removed from the ring.
+    pub eviction_ttl: Duration,
+    /// Turns heartbeat metrics into a health verdict.
+    pub health_policy: Arc<dyn HealthPolicy>,
+    /// Agreement needed before failure reports mark a node unhealthy.
+    pub failure_quorum: FailureQuorum,
+}
+
+impl Default for RingSettings {
+    fn default() -> Self {
+        RingSettings {
+            virtual_nodes: DEFAULT_VIRTUAL_NODES,
+            heartbeat_timeout: DEFAULT_HEARTBEAT_TIMEOUT,
+            eviction_ttl: DEFAULT_EVICTION_TTL,
+            health_policy: Arc::new(ThresholdPolicy::default()),
+            failure_quorum: FailureQuorum::default(),
+        }
+    }
+}
+
+/// One version of a ring, never changed once published. The point lists and
+/// servers are shared between versions until a change touches them, so a health
+/// flip copies `healthy`, the map of servers and the one server flipped, never
+/// the metadata of the others.
+#[derive(Debug, Clone, Default)]
+struct Points {
+    /// Every virtual node as `(position, service_id)`, sorted by position.
+    all: Arc<Vec<(u32, Arc<str>)>>,
+    /// Same as `all` restricted to healthy servers, what lookups search.
+    healthy: Arc<Vec<(u32, Arc<str>)>>,
+    /// Physical servers by service id, `id` being their first position on the ring.
+    servers: Arc<HashMap<Arc<str>, Arc<Node>>>,
+    /// Number of membership changes that led to this version.
+    revision: u64,
+    /// Set once the namespace is deleted.
+    closed: bool,
+}
+
+impl Points {
+    /// The server registered under `service_id`, copied out of the versions it's
+    /// shared with.
+    fn server_mut(&mut self, service_id: &str) -> Option<&mut Node> {
+        Arc::make_mut(&mut self.servers)
+            .get_mut(service_id)
+            .map(Arc::make_mut)
+    }
+
+    /// Brings the points of `service_id` in or out of `healthy`, the server is
+    /// expected to be placed already when it's brought in.
+    fn reindex(&mut self, service_id: &str, healthy: bool) {
+        let current = Arc::make_mut(&mut self.healthy);
+        current.retain(|(_, item)| item.as_ref() != service_id);
+        if !healthy {
+            return;
+        }
+
+        // Merge the server points back in, both sides are sorted already
+        let mut own = self
+            .all
+            .iter()
+            .filter(|(_, item)| item.as_ref() == service_id)
+            .cloned()
+            .peekable();
+        let mut merged = Vec::with_capacity(current.len() + self.all.len());
+        for point in current.drain(..) {
+            while let Some(next) = own.next_if(|(id, _)| *id < point.0) {
+                merged.push(next);
+            }
+            merged.push(point);
+        }
+        merged.extend(own);
+        *current = merged;
+    }
+
+    /// First point of `points` at or clockwise after `position` accepted by
+    /// `accept`, wrapping around.
+    fn clockwise(
+        points: &[(u32, Arc<str>)],
+        position: u32,
+        mut accept: impl FnMut(&(u32, Arc<str>)) -> bool,
+    ) -> Option<&(u32, Arc<str>)> {
+        let i = points.partition_point(|(id, _)| *id < position);
+        let (before, after) = points.split_at(i);
+        after.iter().chain(before).find(|point| accept(point))
+    }
+}
 
 #[derive(Debug)]
 pub struct Ring {
     pub namespace: String,
-    pub nodes: RwLock<Vec<Node>>,
+    /// What the namespace was created with, `settings` is derived from it.
+    pub definition: NamespaceDefinition,
+    pub settings: RingSettings,
+    /// Current version of the ring. Every change swaps in a new copy so lookups
+    /// never wait on writers, they keep whatever version they loaded.
+    points: ArcSwap<Points>,
+    /// Last heartbeat of every server that sent one, kept off the points so a
+    /// heartbeat doesn't copy the ring.
+    heartbeats: Mutex<HashMap<String, Heartbeat>>,
+    /// Held by callers across changes spanning more than one call, e.g. a
+    /// health flip and its bookkeeping. Lookups never take it.
+    pub write_lock: Mutex<()>,
+    /// Membership changes, published once the version they lead to is swapped in.
+    events: broadcast::Sender<MembershipEvent>,
 }
 
-pub fn build(namespace :String,ip_list: Vec<String>) -> Ring {
+pub fn build(namespace: String, definition: NamespaceDefinition) -> Ring {
+    let mut settings = definition.ring_settings();
+    settings.virtual_nodes = settings.virtual_nodes.max(1);
+    let ip_list = definition.nodes.clone();
     let res = Ring {
-        namespace: namespace,
-        nodes: RwLock::new(Vec::new()),
-        // registered_ips: Vec::new(),
+        namespace,
+        definition,
+        settings,
+        points: ArcSwap::from_pointee(Points::default()),
+        heartbeats: Mutex::new(HashMap::new()),
+        write_lock: Mutex::new(()),
+        events: broadcast::channel(WATCH_BUFFER).0,
     };
 
-    let mut nodes_mapper: HashMap<u32, Node> = HashMap::new();
-    let mut node_ids: Vec<u32> = Vec::new();
-
     for ip_addr in ip_list {
-        let node_id = ip_to_hash(&ip_addr);
-
-        let bind_id = ip_addr.clone();
-        nodes_mapper.insert(
-            node_id,
-            Node {
-                id: ip_to_hash(&ip_addr),
-                ip: bind_id,
-                healthy: true,
-            },
-        );
-
         // should do health check here
-        node_ids.push(node_id);
-    }
-
-    node_ids.sort();
+        let server = Node {
+            id: 0,
+            service_id: Ulid::new().to_string(),
+            ip: host_of(&ip_addr),
+            port: port_of(&ip_addr),
+            healthy: true,
+            recovering_beats: 0,
+            metadata: HashMap::new(),
+            owner: None,
+        };
 
-    for id in node_ids {
-        match nodes_mapper.get(&id) {
-            Some(n) => {
-                let mut guard = res.nodes.write().unwrap();
-                guard.push(n.clone());
-            }
-            None => {}
+        // Statically defined nodes aren't expected to heartbeat
+        if let Err(e) = res.insert_server(server) {
+            tracing::error!(ip = %ip_addr, namespace = %res.namespace, error = %e, "failed to add configured node");
         }
     }
 
     res
 }
 
+/// Address the ring positions of a server are derived from.
+pub fn endpoint(ip_addr: &str, port: u32) -> String {
+    if port == 0 {
+        return ip_addr.to_string();
+    }
+
+    format!("{}:{}", ip_addr, port)
+}
+
+/// A membership change made by an update, published as a `MembershipEvent`.
+type Event = (Change, Vec<Node>);
+
+fn not_found() -> ErrorResponse {
+    ErrorResponse::BadRequest("can't find service inside the namespace".to_string())
+}
+
 impl NodePool for Ring {
-    fn get(&self, client_ip_addr: String) -> Result<String, ErrorResponse> {
+    fn get(
+        &self,
+        client_ip_addr: String,
+        selector: &HashMap<String, String>,
+    ) -> Result<Node, ErrorResponse> {
         let client_id = ip_to_hash(&client_ip_addr);
-        let read_nodes = self.nodes.read();
+        let points = self.points.load();
 
-        match read_nodes {
-            Ok(nodes) => {
-                if nodes.len() == 0 {
-                    return Err(ErrorResponse::Internal(
-                        "no service found in namespace".to_string(),
-                    ));
-                }
+        if points.all.is_empty() {
+            return Err(ErrorResponse::Internal(
+                "no service found in namespace".to_string(),
+            ));
+        }
 
-                let pos = nodes
-                    .iter()
-                    .position(|item| (item.id >= client_id && item.healthy));
-
-                match pos {
-                    Some(pos) => return Ok(nodes[pos].id.to_string()),
-                    None => {
-                        // Pick closest node to client_id
-                        let mut closest_id: u32 = 0;
-
-                        for node in nodes.iter() {
-                            if node.id >= client_id {
-                                return Ok(closest_id.to_string());
-                            }
-
-                            if node.healthy {
-                                closest_id = node.id;
-                            }
-                        }
-
-                        return Err(ErrorResponse::Internal(
-                            "no healthy service found in namespace".to_string(),
-                        ));
-                    }
-                }
-            }
-            Err(e) => {
-                return Err(ErrorResponse::Internal(e.to_string()));
-            }
+        // Clockwise from client_id over healthy points only, wrapping around to
+        // the start of the ring when the client hash is past the last one.
+        // With a selector the walk skips servers that don't match, which is the
+        // same as hashing over the matching subset
+        let point = Points::clockwise(&points.healthy, client_id, |(_, service_id)| {
+            selector.is_empty()
+                || points
+                    .servers
+                    .get(service_id)
+                    .is_some_and(|server| server.matches(selector))
+        });
+
+        match point.and_then(|(id, service_id)| Some((id, points.servers.get(service_id)?))) {
+            Some((id, server)) => Ok(Node {
+                id: *id,
+                ..Node::clone(server)
+            }),
+            None if selector.is_empty() => Err(ErrorResponse::Internal(
+                "no healthy service found in namespace".to_string(),
+            )),
+            None => Err(ErrorResponse::Internal(
+                "no healthy service matching the selector found in namespace".to_string(),
+            )),
         }
     }
 
-    fn add_server(&self, ip_addr: String) -> Result<u32, ErrorResponse> {
-        let node_id = ip_to_hash(&ip_addr);
-        let write_nodes = self.nodes.write();
-
-        match write_nodes {
-            Ok(mut nodes) => {
-                // Linear search is just enough to find index for insertion
-                // justification: won't be holding a lot of node inside the vec
-                let pos = nodes.iter().position(|item| item.id >= node_id);
-
-                match pos {
-                    Some(i) if nodes[i].id == node_id => return Ok(node_id),
-                    Some(i) => nodes.insert(
-                        i,
-                        Node {
-                            id: node_id,
-                            ip: ip_addr.clone(),
-                            healthy: true,
-                        },
-                    ),
-                    None => nodes.push(Node {
-                        id: node_id,
-                        ip: ip_addr.clone(),
-                        healthy: true,
-                    }),
+    fn add_server(
+        &self,
+        service_id: String,
+        ip_addr: String,
+        port: u32,
+        metadata: HashMap<String, String>,
+        owner: Option<String>,
+    ) -> Result<String, ErrorResponse> {
+        let service_id = self.insert_server(Node {
+            id: 0,
+            service_id,
+            ip: ip_addr,
+            port,
+            healthy: true,
+            recovering_beats: 0,
+            metadata,
+            owner,
+        })?;
+        self.heartbeats()?
+            .insert(service_id.clone(), Heartbeat::now());
+
+        Ok(service_id)
+    }
+
+    fn set_health_status(&self, service_id: String, is_healthy: bool) -> Result<(), ErrorResponse> {
+        self.update(|points| {
+            // Health is a property of the physical server, every virtual node it
+            // owns follows once the healthy points are rebuilt
+            let (was_healthy, server) = match points.server_mut(&service_id) {
+                Some(server) => {
+                    let was_healthy = server.healthy;
+                    server.healthy = is_healthy;
+                    server.recovering_beats = 0;
+                    (was_healthy, server.clone())
                 }
+                None => return Err(not_found()),
+            };
+            points.reindex(&service_id, is_healthy);
+
+            match was_healthy == is_healthy {
+                true => Ok(((), None)),
+                false => Ok(((), Some((Change::HealthChanged, vec![server])))),
             }
-            Err(e) => {
-                return Err(ErrorResponse::Internal(e.to_string()));
+        })
+    }
+
+    fn remove_server(&self, service_id: String) -> Result<(), ErrorResponse> {
+        self.update(|points| {
+            let server = match Arc::make_mut(&mut points.servers).remove(service_id.as_str()) {
+                Some(server) => Arc::unwrap_or_clone(server),
+                None => return Err(not_found()),
+            };
+            Arc::make_mut(&mut points.all).retain(|(_, item)| item.as_ref() != service_id.as_str());
+            points.reindex(&service_id, false);
+
+            Ok(((), Some((Change::Removed, vec![server]))))
+        })?;
+        self.heartbeats()?.remove(&service_id);
+
+        Ok(())
+    }
+}
+
+impl Ring {
+    fn heartbeats(&self) -> Result<MutexGuard<'_, HashMap<String, Heartbeat>>, ErrorResponse> {
+        self.heartbeats
+            .lock()
+            .map_err(|e| ErrorResponse::Internal(e.to_string()))
+    }
+
+    /// Applies `change` to a copy of the ring and swaps it in, retrying on top
+    /// of the newer ring if another writer got there first. Nothing is swapped
+    /// when `change` fails.
+    ///
+    /// A change reporting an event bumps the revision and the event is published
+    /// to watchers once swapped in.
+    fn update<R>(
+        &self,
+        mut change: impl FnMut(&mut Points) -> Result<(R, Option<Event>), ErrorResponse>,
+    ) -> Result<R, ErrorResponse> {
+        let mut current = self.points.load_full();
+        loop {
+            let mut next = (*current).clone();
+            let (result, event) = change(&mut next)?;
+            if event.is_some() {
+                next.revision += 1;
+            }
+            let revision = next.revision;
+
+            let previous = self.points.compare_and_swap(&current, Arc::new(next));
+            if Arc::ptr_eq(&previous, &current) {
+                if let Some((change, nodes)) = event {
+                    // No watcher listening is not an error
+                    let _ = self.events.send(MembershipEvent {
+                        namespace: self.namespace.clone(),
+                        revision,
+                        change,
+                        nodes,
+                    });
+                }
+                return Ok(result);
             }
+            current = Guard::into_inner(previous);
         }
+    }
 
-        Ok(node_id)
-    }
-
-    fn set_health_status(&self, ip_addr: String, is_healthy: bool) -> Result<(), ErrorResponse> {
-        let write_nodes = self.nodes.write();
-
-        match write_nodes {
-            Ok(mut nodes) => {
-                let node_id = ip_to_hash(&ip_addr);
-                let pos = nodes.iter().position(|item| item.id == node_id);
-                match pos {
-                    Some(pos) => {
-                        match nodes.get_mut(pos) {
-                            Some(node) => {
-                                if node.healthy != is_healthy {
-                                    // Replaces old node with new copy
-                                    nodes[pos] = Node {
-                                        id: node.id,
-                                        ip: node.ip.clone(),
-                                        healthy: is_healthy,
-                                    };
-                                }
-
-                                return Ok(());
-                            }
-                            None => {}
-                        }
-                    }
-                    None => {
-                        return Err(ErrorResponse::BadRequest(
-                            "can't find service inside the namespace".to_string(),
-                        ))
-                    }
+    /// Starts watching the ring: every node as of the returned snapshot, then
+    /// the changes made after it.
+    ///
+    /// Events received with a revision not above the snapshot one are already
+    /// part of it and can be skipped. The snapshot is a `NamespaceDeleted` event
+    /// when the ring was closed already.
+    pub fn subscribe(&self) -> (MembershipEvent, broadcast::Receiver<MembershipEvent>) {
+        // Subscribe first, a change made in between shows up in both at worst
+        let events = self.events.subscribe();
+        let points = self.points.load();
+
+        let mut nodes: Vec<Node> = points
+            .servers
+            .values()
+            .map(|server| Node::clone(server))
+            .collect();
+        nodes.sort_by_key(|server| server.id);
+        let snapshot = MembershipEvent {
+            namespace: self.namespace.clone(),
+            revision: points.revision,
+            change: match points.closed {
+                true => Change::NamespaceDeleted,
+                false => Change::Snapshot,
+            },
+            nodes,
+        };
+
+        (snapshot, events)
+    }
+
+    /// Tells watchers the namespace is gone, the ring is about to be dropped.
+    pub fn close(&self) -> Result<(), ErrorResponse> {
+        self.update(|points| {
+            points.closed = true;
+            Ok(((), Some((Change::NamespaceDeleted, Vec::new()))))
+        })
+    }
+
+    /// Places every virtual node of `server` on the ring, `server.id` is ignored.
+    ///
+    /// Registering an endpoint that's already on the ring returns the service id
+    /// it was first registered with, its metadata is replaced by the new one so a
+    /// redeploy on the same address shows up with its new labels. Only the client
+    /// that registered it may do so, a node of `horbo.yml` goes to the first one.
+    fn insert_server(&self, server: Node) -> Result<String, ErrorResponse> {
+        let key = endpoint(&server.ip, server.port);
+
+        self.update(|points| {
+            let registered = points
+                .servers
+                .values()
+                .find(|item| item.ip == server.ip && item.port == server.port)
+                .map(|item| item.service_id.clone());
+            if let Some(existing) = registered.and_then(|id| points.server_mut(&id)) {
+                if existing.owner.is_some() && existing.owner != server.owner {
+                    return Err(ErrorResponse::Forbidden(
+                        "address is registered by another client".to_string(),
+                    ));
                 }
+                existing.owner = server.owner.clone();
+                if existing.metadata == server.metadata {
+                    return Ok((existing.service_id.clone(), None));
+                }
+                existing.metadata = server.metadata.clone();
+
+                return Ok((
+                    existing.service_id.clone(),
+                    Some((Change::MetadataChanged, vec![existing.clone()])),
+                ));
             }
-            Err(e) => {
-                return Err(ErrorResponse::Internal(e.to_string()));
+
+            let service_id: Arc<str> = Arc::from(server.service_id.as_str());
+            let all = Arc::make_mut(&mut points.all);
+            let mut first: Option<u32> = None;
+            for replica in 0..self.settings.virtual_nodes {
+                let node_id = vnode_hash(&key, replica);
+
+                match all.binary_search_by_key(&node_id, |(id, _)| *id) {
+                    // Point already taken by another server, skip this replica
+                    Ok(_) => continue,
+                    Err(i) => all.insert(i, (node_id, service_id.clone())),
+                }
+                first = Some(first.map_or(node_id, |id| id.min(node_id)));
             }
+
+            // Every position collided, the server can't be placed
+            let first = match first {
+                Some(id) => id,
+                None => {
+                    return Err(ErrorResponse::Internal(
+                        "no free position left on the ring".to_string(),
+                    ))
+                }
+            };
+
+            let placed = Node {
+                id: first,
+                ..server.clone()
+            };
+            Arc::make_mut(&mut points.servers).insert(service_id, Arc::new(placed.clone()));
+            points.reindex(&server.service_id, server.healthy);
+
+            Ok((
+                server.service_id.clone(),
+                Some((Change::Added, vec![placed])),
+            ))
+        })
+    }
+
+    /// A copy of the server registered under `service_id`, if any.
+    pub fn server(&self, service_id: &str) -> Option<Node> {
+        self.points
+            .load()
+            .servers
+            .get(service_id)
+            .map(|server| Node::clone(server))
+    }
+
+    /// A copy of every physical server on the ring, in ring order of their first point.
+    pub fn servers(&self) -> Vec<Node> {
+        let mut servers: Vec<Node> = self
+            .points
+            .load()
+            .servers
+            .values()
+            .map(|server| Node::clone(server))
+            .collect();
+        servers.sort_by_key(|server| server.id);
+        servers
+    }
+
+    /// Whether the namespace was deleted, the ring is no longer in `service_map` then.
+    pub fn closed(&self) -> bool {
+        self.points.load().closed
+    }
+
+    /// Number of physical servers on the ring.
+    pub fn server_count(&self) -> usize {
+        self.points.load().servers.len()
+    }
+
+    /// Number of physical servers on the ring currently healthy.
+    pub fn healthy_server_count(&self) -> usize {
+        self.points
+            .load()
+            .servers
+            .values()
+            .filter(|server| server.healthy)
+            .count()
+    }
+
+    /// Number of points on the ring, healthy or not.
+    pub fn point_count(&self) -> usize {
+        self.points.load().all.len()
+    }
+
+    /// Finds the service id of a server given either its service id, its
+    /// advertised host or its `host:port` endpoint.
+    pub fn resolve(&self, key: &str) -> Option<String> {
+        let points = self.points.load();
+        if points.servers.contains_key(key) {
+            return Some(key.to_string());
         }
 
-        Ok(())
+        points
+            .servers
+            .values()
+            .filter(|server| server.ip == key || endpoint(&server.ip, server.port) == key)
+            .min_by_key(|server| server.id)
+            .map(|server| server.service_id.clone())
     }
-    
-    fn remove_server(&self, _: String) -> Result<(), ErrorResponse> {
-        todo!()
+
+    /// Latest heartbeat of the server, `None` if it never sent one.
+    pub fn last_heartbeat(&self, service_id: &str) -> Option<Heartbeat> {
+        match self.heartbeats() {
+            Ok(heartbeats) => heartbeats.get(service_id).copied(),
+            Err(_) => None,
+        }
     }
-}
 
-impl Ring {
-    pub fn repr(&self) -> Vec<NodeGrpc> {
-        let read_nodes = self.nodes.read();
-        let mut result:Vec< NodeGrpc> = Vec::new();
-
-        match read_nodes {
-            Ok(nodes) => {
-                for node in nodes.iter() {
-                    result.push(NodeGrpc{
-                        id:node.id.to_string(),
-                        ip_address: node.ip.clone(),
-                        namespace: self.namespace.clone(),
-                    });
-                }
+    /// Every physical server with its latest heartbeat, in ring order of their first point.
+    pub fn instances(&self) -> Vec<(Node, Option<Heartbeat>)> {
+        let servers = self.servers();
+        let heartbeats = match self.heartbeats() {
+            Ok(heartbeats) => heartbeats,
+            Err(_) => return servers.into_iter().map(|server| (server, None)).collect(),
+        };
+
+        servers
+            .into_iter()
+            .map(|server| {
+                let heartbeat = heartbeats.get(&server.service_id).copied();
+                (server, heartbeat)
+            })
+            .collect()
+    }
+
+    /// Stamps the server with the current time, keeping its latest metric.
+    pub fn record_heartbeat(&self, service_id: &str) -> Result<(), ErrorResponse> {
+        if self.server(service_id).is_none() {
+            return Err(not_found());
+        }
 
-                return result
+        let mut heartbeats = self.heartbeats()?;
+        let metric = heartbeats.get(service_id).and_then(|beat| beat.metric);
+        heartbeats.insert(
+            service_id.to_string(),
+            Heartbeat {
+                at: Instant::now(),
+                metric,
             },
-            Err(_) => {
-                /* Don't return error as this func is used
-                in each response to a heartbeat */
-                return result
+        );
+
+        Ok(())
+    }
+
+    /// Runs the namespace health policy against a heartbeat metric and applies
+    /// the verdict to the server.
+    /// Returns the health before and after, as `(was_healthy, is_healthy)`.
+    pub fn apply_metric(
+        &self,
+        service_id: &str,
+        metric: &UtilizationMetric,
+    ) -> Result<(bool, bool), ErrorResponse> {
+        let current = match self.server(service_id) {
+            Some(node) => NodeHealth {
+                healthy: node.healthy,
+                recovering_beats: node.recovering_beats,
+            },
+            None => return Err(not_found()),
+        };
+
+        if let Some(beat) = self.heartbeats()?.get_mut(service_id) {
+            beat.metric = Some(*metric);
+        }
+
+        let next = self.settings.health_policy.evaluate(current, metric);
+        // Steady state, no need to copy the ring
+        if next == current {
+            return Ok((current.healthy, next.healthy));
+        }
+
+        self.update(|points| {
+            let server = match points.server_mut(service_id) {
+                Some(server) => {
+                    server.healthy = next.healthy;
+                    server.recovering_beats = next.recovering_beats;
+                    server.clone()
+                }
+                None => return Err(not_found()),
+            };
+            if current.healthy == next.healthy {
+                return Ok(((), None));
             }
+            points.reindex(service_id, next.healthy);
+
+            Ok(((), Some((Change::HealthChanged, vec![server]))))
+        })?;
+
+        Ok((current.healthy, next.healthy))
+    }
+
+    /// Servers whose last heartbeat is older than `timeout`, as `(service_id, healthy)`.
+    /// Nodes that never sent a heartbeat are not considered.
+    pub fn silent_servers(&self, timeout: Duration) -> Vec<(String, bool)> {
+        let silent: Vec<String> = match self.heartbeats() {
+            Ok(heartbeats) => heartbeats
+                .iter()
+                .filter(|(_, beat)| beat.at.elapsed() >= timeout)
+                .map(|(service_id, _)| service_id.clone())
+                .collect(),
+            Err(_) => return Vec::new(),
+        };
+
+        silent
+            .into_iter()
+            .filter_map(|service_id| {
+                let server = self.server(&service_id)?;
+                Some((service_id, server.healthy))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(points: &[(u32, &str)]) -> Vec<(u32, Arc<str>)> {
+        points
+            .iter()
+            .map(|(id, service_id)| (*id, Arc::from(*service_id)))
+            .collect()
+    }
+
+    fn points(all: &[(u32, &str)]) -> Points {
+        Points {
+            all: Arc::new(layout(all)),
+            healthy: Arc::new(layout(all)),
+            ..Points::default()
         }
     }
-}
\ No newline at end of file
+
+    fn ring(virtual_nodes: u32) -> Ring {
+        let definition =
+            serde_yaml::from_str(&format!("virtual_nodes: {}", virtual_nodes)).unwrap();
+        build("payment".to_string(), definition)
+    }
+
+    fn labels(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn clockwise_takes_the_point_at_or_after_the_position() {
+        let all = layout(&[(10, "a"), (20, "b"), (30, "c")]);
+        let owner =
+            |position| Points::clockwise(&all, position, |_| true).map(|(_, id)| id.to_string());
+
+        assert_eq!(owner(0).as_deref(), Some("a"));
+        assert_eq!(owner(15).as_deref(), Some("b"));
+        assert_eq!(owner(20).as_deref(), Some("b"));
+    }
+
+    #[test]
+    fn clockwise_wraps_past_the_last_point() {
+        let all = layout(&[(10, "a"), (20, "b"), (30, "c")]);
+        let (_, owner) = Points::clockwise(&all, 31, |_| true).unwrap();
+
+        assert_eq!(owner.as_ref(), "a");
+        assert!(Points::clockwise(&[], 31, |_| true).is_none());
+    }
+
+    #[test]
+    fn clockwise_walks_past_rejected_points() {
+        let all = layout(&[(10, "a"), (20, "b"), (30, "c")]);
+        let only = |wanted: &'static str| move |(_, id): &(u32, Arc<str>)| id.as_ref() == wanted;
+
+        assert_eq!(Points::clockwise(&all, 5, only("c")).unwrap().0, 30);
+        assert_eq!(Points::clockwise(&all, 25, only("a")).unwrap().0, 10);
+        assert!(Points::clockwise(&all, 25, only("d")).is_none());
+    }
+
+    #[test]
+    fn reindex_keeps_healthy_sorted_across_health_flips() {
+        let mut points = points(&[(10, "a"), (20, "b"), (30, "a"), (40, "c"), (50, "b")]);
+
+        points.reindex("a", false);
+        assert_eq!(*points.healthy, layout(&[(20, "b"), (40, "c"), (50, "b")]));
+
+        points.reindex("b", false);
+        points.reindex("a", true);
+        assert_eq!(*points.healthy, layout(&[(10, "a"), (30, "a"), (40, "c")]));
+
+        points.reindex("b", true);
+        assert_eq!(points.healthy, points.all);
+    }
+
+    #[test]
+    fn reindex_of_a_healthy_server_changes_nothing() {
+        let mut points = points(&[(10, "a"), (20, "b")]);
+        points.reindex("a", true);
+
+        assert_eq!(points.healthy, points.all);
+    }
+
+    #[test]
+    fn removing_a_server_drops_every_virtual_node() {
+        let ring = ring(16);
+        let a = ring
+            .add_server(
+                "a".to_string(),
+                "10.0.0.1".to_string(),
+                5000,
+                HashMap::new(),
+                None,
+            )
+            .unwrap();
+        let b = ring
+            .add_server(
+                "b".to_string(),
+                "10.0.0.2".to_string(),
+                5000,
+                HashMap::new(),
+                None,
+            )
+            .unwrap();
+        let owned = |points: &[(u32, Arc<str>)], id: &str| {
+            points
+                .iter()
+                .filter(|(_, item)| item.as_ref() == id)
+                .count()
+        };
+        let placed = ring.point_count();
+        let of_a = owned(&ring.points.load().all, &a);
+
+        ring.remove_server(a.clone()).unwrap();
+
+        let points = ring.points.load();
+        assert!(of_a > 1);
+        assert_eq!(ring.point_count(), placed - of_a);
+        assert_eq!(owned(&points.all, &a) + owned(&points.healthy, &a), 0);
+        assert_eq!(owned(&points.healthy, &b), points.all.len());
+        assert!(ring.server(&a).is_none());
+    }
+
+    #[test]
+    fn lookup_past_the_last_point_wraps_to_the_first() {
+        let ring = ring(4);
+        ring.add_server(
+            "a".to_string(),
+            "10.0.0.1".to_string(),
+            5000,
+            HashMap::new(),
+            None,
+        )
+        .unwrap();
+        ring.add_server(
+            "b".to_string(),
+            "10.0.0.2".to_string(),
+            5000,
+            HashMap::new(),
+            None,
+        )
+        .unwrap();
+
+        let points = ring.points.load();
+        let (last, _) = points.all.last().unwrap();
+        let client = (0..)
+            .map(|i| format!("192.168.{}.{}", i / 256, i % 256))
+            .find(|client| ip_to_hash(client) > *last)
+            .unwrap();
+
+        let node = ring.get(client, &HashMap::new()).unwrap();
+        assert_eq!(node.id, points.all[0].0);
+    }
+
+    #[test]
+    fn selector_walks_to_the_next_matching_server() {
+        let ring = ring(8);
+        ring.add_server(
+            "a".to_string(),
+            "10.0.0.1".to_string(),
+            5000,
+            labels(&[("zone", "a")]),
+            None,
+        )
+        .unwrap();
+        ring.add_server(
+            "b".to_string(),
+            "10.0.0.2".to_string(),
+            5000,
+            labels(&[("zone", "b")]),
+            None,
+        )
+        .unwrap();
+
+        for i in 0..64 {
+            let client = format!("192.168.0.{}", i);
+            let node = ring.get(client, &labels(&[("zone", "b")])).unwrap();
+            assert_eq!(node.service_id, "b");
+        }
+
+        ring.set_health_status("b".to_string(), false).unwrap();
+        assert!(ring
+            .get("192.168.0.1".to_string(), &labels(&[("zone", "b")]))
+            .is_err());
+        assert_eq!(
+            ring.get("192.168.0.1".to_string(), &HashMap::new())
+                .unwrap()
+                .service_id,
+            "a"
+        );
+    }
+}
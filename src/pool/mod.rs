@@ -1,2 +1,3 @@
+pub mod consistent_hash;
+#[allow(clippy::module_inception)]
 pub mod pool;
-pub mod consistent_hash;
\ No newline at end of file
@@ -1,8 +1,23 @@
+use std::collections::HashMap;
+
 use crate::common::error::ErrorResponse;
+use crate::core::domain::data::Node;
 
 pub trait NodePool {
-    fn get(&self, client_ip_addr: String) -> Result<String, ErrorResponse> ;
-    fn add_server(&self, ip_addr: String) -> Result<u32, ErrorResponse>;
-    fn remove_server(&self, ip_addr: String) -> Result<(), ErrorResponse>;
-    fn set_health_status(&self, ip_addr: String, is_healthy: bool) -> Result<(), ErrorResponse>;
-}
\ No newline at end of file
+    /// Picks a node for the client among the ones carrying every label of `selector`.
+    fn get(
+        &self,
+        client_ip_addr: String,
+        selector: &HashMap<String, String>,
+    ) -> Result<Node, ErrorResponse>;
+    fn add_server(
+        &self,
+        service_id: String,
+        ip_addr: String,
+        port: u32,
+        metadata: HashMap<String, String>,
+        owner: Option<String>,
+    ) -> Result<String, ErrorResponse>;
+    fn remove_server(&self, service_id: String) -> Result<(), ErrorResponse>;
+    fn set_health_status(&self, service_id: String, is_healthy: bool) -> Result<(), ErrorResponse>;
+}
@@ -1,27 +1,35 @@
 //
 use std::{future::Future, pin::Pin, sync::Arc};
-use tokio::sync::Mutex;
+use tokio_stream::{wrappers::ReceiverStream, Stream, StreamExt};
 
 use tonic::{Request, Response, Status};
+use tracing::Span;
 
 use crate::{
+    auth::policy::{AuthPolicy, Caller, Operation},
+    common::error::ErrorResponse,
     core::{
         application::service_discovery::ServiceDiscovery,
         domain::{data::UtilizationMetric, server::ServiceDiscoveryUsecase},
+        schema::NamespaceDefinition,
     },
     grpc::{horbo_server::Horbo, *},
+    telemetry::{logging, registry},
 };
 
+/// Descriptors of `proto/messages.proto`, served by gRPC reflection.
+pub const FILE_DESCRIPTOR_SET: &[u8] =
+    include_bytes!(concat!(env!("OUT_DIR"), "/horbo_descriptor.bin"));
+
 pub struct HorboServiceController {
-    pub service: Arc<Mutex<ServiceDiscovery>>,
+    pub service: Arc<ServiceDiscovery>,
+    pub auth: Arc<AuthPolicy>,
 }
 
 impl Horbo for HorboServiceController {
-    #[allow(
-        elided_named_lifetimes,
-        clippy::type_complexity,
-        clippy::type_repetition_in_bounds
-    )]
+    type WatchStream = Pin<Box<dyn Stream<Item = Result<WatchEvent, Status>> + Send>>;
+
+    #[allow(clippy::type_complexity, clippy::type_repetition_in_bounds)]
     fn register_agent<'life0, 'async_trait>(
         &'life0 self,
         request: Request<AgentRegistrationRequest>,
@@ -36,14 +44,44 @@ impl Horbo for HorboServiceController {
         'life0: 'async_trait,
         Self: 'async_trait,
     {
-        Box::pin(self.register_node(request))
+        let span = logging::rpc_span(
+            "register_agent",
+            &request.get_ref().namespace,
+            "",
+            request.remote_addr(),
+        );
+        Box::pin(observed(
+            "register_agent",
+            span,
+            self.register_node(request),
+        ))
+    }
+
+    #[allow(clippy::type_complexity, clippy::type_repetition_in_bounds)]
+    fn deregister_agent<'life0, 'async_trait>(
+        &'life0 self,
+        request: Request<AgentDeregistrationRequest>,
+    ) -> Pin<
+        Box<dyn Future<Output = std::result::Result<Response<()>, Status>> + Send + 'async_trait>,
+    >
+    where
+        'life0: 'async_trait,
+        Self: 'async_trait,
+    {
+        let span = logging::rpc_span(
+            "deregister_agent",
+            &request.get_ref().namespace,
+            &request.get_ref().service_id,
+            request.remote_addr(),
+        );
+        Box::pin(observed(
+            "deregister_agent",
+            span,
+            self.deregister_node(request),
+        ))
     }
 
-    #[allow(
-        elided_named_lifetimes,
-        clippy::type_complexity,
-        clippy::type_repetition_in_bounds
-    )]
+    #[allow(clippy::type_complexity, clippy::type_repetition_in_bounds)]
     fn service_lookup<'life0, 'async_trait>(
         &'life0 self,
         request: tonic::Request<LookupRequest>,
@@ -59,14 +97,20 @@ impl Horbo for HorboServiceController {
         'life0: 'async_trait,
         Self: 'async_trait,
     {
-        Box::pin(self.service_lookup(request))
+        let span = logging::rpc_span(
+            "service_lookup",
+            &request.get_ref().namespace,
+            "",
+            request.remote_addr(),
+        );
+        Box::pin(observed(
+            "service_lookup",
+            span,
+            self.service_lookup(request),
+        ))
     }
 
-    #[allow(
-        elided_named_lifetimes,
-        clippy::type_complexity,
-        clippy::type_repetition_in_bounds
-    )]
+    #[allow(clippy::type_complexity, clippy::type_repetition_in_bounds)]
     fn service_failure_report<'life0, 'async_trait>(
         &'life0 self,
         request: tonic::Request<FailureReportRequest>,
@@ -82,14 +126,20 @@ impl Horbo for HorboServiceController {
         'life0: 'async_trait,
         Self: 'async_trait,
     {
-        Box::pin(self.handle_failure_report(request))
+        let span = logging::rpc_span(
+            "service_failure_report",
+            &request.get_ref().namespace,
+            &request.get_ref().service_id,
+            request.remote_addr(),
+        );
+        Box::pin(observed(
+            "service_failure_report",
+            span,
+            self.handle_failure_report(request),
+        ))
     }
 
-    #[allow(
-        elided_named_lifetimes,
-        clippy::type_complexity,
-        clippy::type_repetition_in_bounds
-    )]
+    #[allow(clippy::type_complexity, clippy::type_repetition_in_bounds)]
     fn heartbeat<'life0, 'async_trait>(
         &'life0 self,
         request: tonic::Request<HeartbeatRequest>,
@@ -105,35 +155,250 @@ impl Horbo for HorboServiceController {
         'life0: 'async_trait,
         Self: 'async_trait,
     {
-        Box::pin(self.heartbeat(request))
+        let span = logging::rpc_span(
+            "heartbeat",
+            &request.get_ref().namespace,
+            &request.get_ref().service_id,
+            request.remote_addr(),
+        );
+        Box::pin(observed("heartbeat", span, self.heartbeat(request)))
+    }
+
+    #[allow(clippy::type_complexity, clippy::type_repetition_in_bounds)]
+    fn create_namespace<'life0, 'async_trait>(
+        &'life0 self,
+        request: Request<CreateNamespaceRequest>,
+    ) -> Pin<
+        Box<
+            dyn Future<Output = std::result::Result<Response<NamespaceDescription>, Status>>
+                + Send
+                + 'async_trait,
+        >,
+    >
+    where
+        'life0: 'async_trait,
+        Self: 'async_trait,
+    {
+        let span = logging::rpc_span(
+            "create_namespace",
+            &request.get_ref().namespace,
+            "",
+            request.remote_addr(),
+        );
+        Box::pin(observed(
+            "create_namespace",
+            span,
+            self.create_namespace(request),
+        ))
+    }
+
+    #[allow(clippy::type_complexity, clippy::type_repetition_in_bounds)]
+    fn list_namespaces<'life0, 'async_trait>(
+        &'life0 self,
+        request: Request<()>,
+    ) -> Pin<
+        Box<
+            dyn Future<Output = std::result::Result<Response<ListNamespacesResponse>, Status>>
+                + Send
+                + 'async_trait,
+        >,
+    >
+    where
+        'life0: 'async_trait,
+        Self: 'async_trait,
+    {
+        let span = logging::rpc_span("list_namespaces", "", "", request.remote_addr());
+        Box::pin(observed(
+            "list_namespaces",
+            span,
+            self.list_namespaces(request),
+        ))
+    }
+
+    #[allow(clippy::type_complexity, clippy::type_repetition_in_bounds)]
+    fn describe_namespace<'life0, 'async_trait>(
+        &'life0 self,
+        request: Request<NamespaceRequest>,
+    ) -> Pin<
+        Box<
+            dyn Future<Output = std::result::Result<Response<NamespaceDescription>, Status>>
+                + Send
+                + 'async_trait,
+        >,
+    >
+    where
+        'life0: 'async_trait,
+        Self: 'async_trait,
+    {
+        let span = logging::rpc_span(
+            "describe_namespace",
+            &request.get_ref().namespace,
+            "",
+            request.remote_addr(),
+        );
+        Box::pin(observed(
+            "describe_namespace",
+            span,
+            self.describe_namespace(request),
+        ))
+    }
+
+    #[allow(clippy::type_complexity, clippy::type_repetition_in_bounds)]
+    fn delete_namespace<'life0, 'async_trait>(
+        &'life0 self,
+        request: Request<NamespaceRequest>,
+    ) -> Pin<
+        Box<dyn Future<Output = std::result::Result<Response<()>, Status>> + Send + 'async_trait>,
+    >
+    where
+        'life0: 'async_trait,
+        Self: 'async_trait,
+    {
+        let span = logging::rpc_span(
+            "delete_namespace",
+            &request.get_ref().namespace,
+            "",
+            request.remote_addr(),
+        );
+        Box::pin(observed(
+            "delete_namespace",
+            span,
+            self.delete_namespace(request),
+        ))
+    }
+
+    #[allow(clippy::type_complexity, clippy::type_repetition_in_bounds)]
+    fn watch<'life0, 'async_trait>(
+        &'life0 self,
+        request: Request<NamespaceRequest>,
+    ) -> Pin<
+        Box<
+            dyn Future<Output = std::result::Result<Response<Self::WatchStream>, Status>>
+                + Send
+                + 'async_trait,
+        >,
+    >
+    where
+        'life0: 'async_trait,
+        Self: 'async_trait,
+    {
+        let span = logging::rpc_span(
+            "watch",
+            &request.get_ref().namespace,
+            "",
+            request.remote_addr(),
+        );
+        Box::pin(observed("watch", span, self.watch_namespace(request)))
+    }
+
+    #[allow(clippy::type_complexity, clippy::type_repetition_in_bounds)]
+    fn list_instances<'life0, 'async_trait>(
+        &'life0 self,
+        request: Request<ListInstancesRequest>,
+    ) -> Pin<
+        Box<
+            dyn Future<Output = std::result::Result<Response<ListInstancesResponse>, Status>>
+                + Send
+                + 'async_trait,
+        >,
+    >
+    where
+        'life0: 'async_trait,
+        Self: 'async_trait,
+    {
+        let span = logging::rpc_span(
+            "list_instances",
+            &request.get_ref().namespace,
+            "",
+            request.remote_addr(),
+        );
+        Box::pin(observed(
+            "list_instances",
+            span,
+            self.list_instances(request),
+        ))
+    }
+}
+
+/// Runs a handler in its RPC span, logging and counting how it ended.
+async fn observed<T, F>(method: &'static str, span: Span, call: F) -> Result<Response<T>, Status>
+where
+    F: Future<Output = Result<Response<T>, Status>>,
+{
+    logging::traced(span, registry::observe(method, call)).await
+}
+
+/// Unknown or invalid namespaces are the caller's fault, anything else is ours.
+fn to_status(e: ErrorResponse) -> Status {
+    match e {
+        ErrorResponse::BadRequest(e) => Status::invalid_argument(e),
+        ErrorResponse::Unavailable(e) => Status::unavailable(e),
+        ErrorResponse::Forbidden(e) => Status::permission_denied(e),
+        e => Status::internal(e.to_string()),
     }
 }
 
 impl HorboServiceController {
+    /// Identity the auth interceptor attached to the call, empty without auth.
+    fn caller<T>(request: &Request<T>) -> Caller {
+        request
+            .extensions()
+            .get::<Caller>()
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Tells clients apart: the certificate name or API key they authenticated with.
+    /// Peer addresses are shared by every client behind a gateway, so they're only a
+    /// fallback without auth, by host as the source port changes per connection.
+    fn identity<T>(&self, caller: &Caller, request: &Request<T>) -> Result<String, Status> {
+        match self.auth.identity(caller) {
+            Some(identity) => Ok(identity),
+            None => match request.remote_addr() {
+                Some(ip) => Ok(ip.ip().to_string()),
+                None => Err(Status::invalid_argument("ip is not valid")),
+            },
+        }
+    }
+
+    fn authorize<T>(
+        &self,
+        request: &Request<T>,
+        namespace: Option<&str>,
+        operation: Operation,
+    ) -> Result<(), Status> {
+        self.auth
+            .authorize(&Self::caller(request), namespace, operation)
+    }
+
     async fn handle_failure_report(
         &self,
         request: Request<FailureReportRequest>,
     ) -> Result<Response<()>, Status> {
-        let ip_address = request.remote_addr();
+        self.authorize(
+            &request,
+            Some(&request.get_ref().namespace),
+            Operation::ReportFailure,
+        )?;
 
-        match ip_address {
-            Some(_) => {
-                let services = self.service.lock().await;
-                let req_inner = request.into_inner();
+        let reporter = self.identity(&Self::caller(&request), &request)?;
 
-                let res = services
-                    .mark_node_unhealthy(req_inner.namespace, req_inner.ip_address)
-                    .await;
-                match res {
-                    Ok(_) => return Ok(().into()),
-                    Err(e) => {
-                        return Err(Status::internal(e.to_string()));
-                    }
-                }
-            }
-            None => {
-                return Err(Status::invalid_argument("ip is not valid"));
-            }
+        let services = &self.service;
+        let req_inner = request.into_inner();
+
+        /* Prefer the service id, reporters that only know the address still work */
+        let node = if req_inner.service_id.is_empty() {
+            req_inner.ip_address
+        } else {
+            req_inner.service_id
+        };
+
+        let res = services
+            .mark_node_unhealthy(req_inner.namespace, node, reporter)
+            .await;
+        match res {
+            Ok(_) => Ok(().into()),
+            Err(e) => Err(to_status(e)),
         }
     }
 
@@ -141,36 +406,41 @@ impl HorboServiceController {
         &self,
         request: Request<HeartbeatRequest>,
     ) -> Result<Response<HeartbeatResponse>, Status> {
-        let ip_address = request.remote_addr();
+        self.authorize(
+            &request,
+            Some(&request.get_ref().namespace),
+            Operation::Register,
+        )?;
 
-        match ip_address {
-            Some(ip) => {
-                let services = self.service.lock().await;
-                let req_inner = request.into_inner();
+        let caller = Self::caller(&request);
+        let owner = self.identity(&caller, &request)?;
+        let req_inner = request.into_inner();
+        if req_inner.service_id.is_empty() {
+            return Err(Status::invalid_argument("service id is required"));
+        }
 
-                let res = services
-                    .node_heartbeat(
-                        req_inner.namespace.clone(),
-                        ip.to_string(),
-                        UtilizationMetric {
-                            cpu_usage: req_inner.cpu_usage,
-                            memory_usage: req_inner.memory_usage,
-                        },
-                    )
-                    .await;
+        let services = &self.service;
+        let res = services
+            .node_heartbeat(
+                req_inner.namespace.clone(),
+                req_inner.service_id,
+                UtilizationMetric {
+                    cpu_usage: req_inner.cpu_usage,
+                    memory_usage: req_inner.memory_usage,
+                },
+                Some(owner),
+            )
+            .await;
 
-                match res {
-                    Ok(unhealthy_nodes) => {
-                        return Ok(Response::new(unhealthy_nodes))
-                    }
-                    Err(e) => {
-                        return Err(Status::internal(e.to_string()));
-                    }
-                }
-            }
-            None => {
-                return Err(Status::invalid_argument("ip is not valid"));
+        match res {
+            Ok(mut unhealthy_nodes) => {
+                /* Only gossip the namespaces the agent was granted */
+                unhealthy_nodes
+                    .unhealthy_services
+                    .retain(|nodes| self.auth.grants(&caller, &nodes.namespace));
+                Ok(Response::new(unhealthy_nodes))
             }
+            Err(e) => Err(to_status(e)),
         }
     }
 
@@ -178,28 +448,60 @@ impl HorboServiceController {
         &self,
         request: Request<LookupRequest>,
     ) -> Result<Response<LookupResponse>, Status> {
+        self.authorize(
+            &request,
+            Some(&request.get_ref().namespace),
+            Operation::Lookup,
+        )?;
+
         let client_ip_address = request.remote_addr();
 
         match client_ip_address {
             Some(ip) => {
-                let services = self.service.lock().await;
+                let services = &self.service;
                 let req_inner = request.into_inner();
 
+                /* Hash on the client host only, the source port changes per connection */
                 let lookup_response = services
-                    .service_lookup(req_inner.namespace.clone(), ip.to_string())
+                    .service_lookup(
+                        req_inner.namespace.clone(),
+                        ip.ip().to_string(),
+                        req_inner.selector,
+                    )
                     .await;
                 match lookup_response {
                     Ok(lookup_response) => {
-                        return Ok(Response::new(lookup_response));
-                    }
-                    Err(e) => {
-                        return Err(Status::internal(e.to_string()));
+                        /* The node handed out, not one named by the caller */
+                        Span::current().record("service_id", lookup_response.service_id.as_str());
+                        Ok(Response::new(lookup_response))
                     }
+                    Err(e) => Err(to_status(e)),
                 }
             }
-            None => {
-                return Err(Status::invalid_argument("client ip is not valid"));
-            }
+            None => Err(Status::invalid_argument("client ip is not valid")),
+        }
+    }
+
+    async fn deregister_node(
+        &self,
+        request: Request<AgentDeregistrationRequest>,
+    ) -> Result<Response<()>, Status> {
+        self.authorize(
+            &request,
+            Some(&request.get_ref().namespace),
+            Operation::Register,
+        )?;
+
+        let owner = self.identity(&Self::caller(&request), &request)?;
+        let services = &self.service;
+        let req_inner = request.into_inner();
+
+        let res = services
+            .deregister_node(req_inner.namespace, req_inner.service_id, Some(owner))
+            .await;
+        match res {
+            Ok(_) => Ok(().into()),
+            Err(e) => Err(to_status(e)),
         }
     }
 
@@ -207,24 +509,182 @@ impl HorboServiceController {
         &self,
         request: Request<AgentRegistrationRequest>,
     ) -> Result<Response<AgentRegistrationResponse>, Status> {
+        /* Registration may also carry its key in the body, next to the one in metadata */
+        let mut caller = Self::caller(&request);
+        if !request.get_ref().api_key.is_empty() {
+            caller.extend(self.auth.identify(None, Some(&request.get_ref().api_key))?);
+        }
+        self.auth.authorize(
+            &caller,
+            Some(&request.get_ref().namespace),
+            Operation::Register,
+        )?;
+
+        let owner = self.identity(&caller, &request)?;
         let ip_address = request.remote_addr();
 
         match ip_address {
             Some(ip) => {
-                let services = self.service.lock().await;
+                let services = &self.service;
                 let req_inner = request.into_inner();
+
+                /* Peer address is only a fallback, behind NAT or a sidecar it's not dialable */
+                let host = if req_inner.advertise_host.is_empty() {
+                    ip.ip().to_string()
+                } else {
+                    req_inner.advertise_host
+                };
+
                 let response = services
-                    .register_node(req_inner.namespace, ip.to_string())
+                    .register_node(
+                        req_inner.namespace,
+                        host,
+                        req_inner.port,
+                        req_inner.metadata,
+                        owner,
+                    )
                     .await;
 
                 match response {
-                    Ok(id) => Ok(Response::new(id)),
-                    Err(_) => Err(Status::invalid_argument("namespace doesn't exists")),
+                    Ok(id) => {
+                        Span::current().record("service_id", id.service_id.as_str());
+                        Ok(Response::new(id))
+                    }
+                    Err(e) => Err(to_status(e)),
                 }
             }
-            None => {
-                return Err(Status::invalid_argument("ip is not valid"));
-            }
+            None => Err(Status::invalid_argument("ip is not valid")),
         }
     }
+    async fn create_namespace(
+        &self,
+        request: Request<CreateNamespaceRequest>,
+    ) -> Result<Response<NamespaceDescription>, Status> {
+        self.authorize(
+            &request,
+            Some(&request.get_ref().namespace),
+            Operation::Admin,
+        )?;
+
+        let services = &self.service;
+        let req_inner = request.into_inner();
+
+        services
+            .create_namespace(
+                req_inner.namespace,
+                NamespaceDefinition::from_grpc(req_inner.settings),
+            )
+            .await
+            .map(Response::new)
+            .map_err(to_status)
+    }
+
+    async fn list_namespaces(
+        &self,
+        request: Request<()>,
+    ) -> Result<Response<ListNamespacesResponse>, Status> {
+        self.authorize(&request, None, Operation::Admin)?;
+
+        let caller = Self::caller(&request);
+        let services = &self.service;
+
+        let mut response = services.list_namespaces().await.map_err(to_status)?;
+        /* Admin of some namespaces doesn't get to see the others */
+        response.namespaces.retain(|namespace| {
+            self.auth
+                .allows(&caller, &namespace.namespace, Operation::Admin)
+        });
+        Ok(Response::new(response))
+    }
+
+    async fn describe_namespace(
+        &self,
+        request: Request<NamespaceRequest>,
+    ) -> Result<Response<NamespaceDescription>, Status> {
+        self.authorize(
+            &request,
+            Some(&request.get_ref().namespace),
+            Operation::Admin,
+        )?;
+
+        let services = &self.service;
+        let req_inner = request.into_inner();
+
+        services
+            .describe_namespace(req_inner.namespace)
+            .await
+            .map(Response::new)
+            .map_err(to_status)
+    }
+
+    async fn delete_namespace(
+        &self,
+        request: Request<NamespaceRequest>,
+    ) -> Result<Response<()>, Status> {
+        self.authorize(
+            &request,
+            Some(&request.get_ref().namespace),
+            Operation::Admin,
+        )?;
+
+        let services = &self.service;
+        let req_inner = request.into_inner();
+
+        services
+            .delete_namespace(req_inner.namespace)
+            .await
+            .map(Response::new)
+            .map_err(to_status)
+    }
+
+    async fn watch_namespace(
+        &self,
+        request: Request<NamespaceRequest>,
+    ) -> Result<Response<<Self as Horbo>::WatchStream>, Status> {
+        self.authorize(
+            &request,
+            Some(&request.get_ref().namespace),
+            Operation::Lookup,
+        )?;
+
+        let services = &self.service;
+        let req_inner = request.into_inner();
+
+        let events = services
+            .watch(req_inner.namespace)
+            .await
+            .map_err(to_status)?;
+
+        /* Ends with the watcher task, i.e. once the namespace is deleted or on shutdown */
+        let stream = ReceiverStream::new(events)
+            .map(|event| event.map(|event| event.repr()).map_err(to_status));
+
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn list_instances(
+        &self,
+        request: Request<ListInstancesRequest>,
+    ) -> Result<Response<ListInstancesResponse>, Status> {
+        self.authorize(
+            &request,
+            Some(&request.get_ref().namespace),
+            Operation::Lookup,
+        )?;
+
+        let services = &self.service;
+        let req_inner = request.into_inner();
+
+        let healthy = match req_inner.health() {
+            HealthFilter::Any => None,
+            HealthFilter::Healthy => Some(true),
+            HealthFilter::Unhealthy => Some(false),
+        };
+
+        services
+            .list_instances(req_inner.namespace, healthy, req_inner.labels)
+            .await
+            .map(Response::new)
+            .map_err(to_status)
+    }
 }
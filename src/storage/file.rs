@@ -0,0 +1,331 @@
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::common::error::ErrorResponse;
+use crate::storage::storage::{Record, Snapshot, Storage};
+
+const WAL_FILE: &str = "horbo.wal";
+const SNAPSHOT_FILE: &str = "horbo.snapshot";
+
+/// Local disk storage, a write-ahead log of JSON lines next to a JSON snapshot.
+///
+/// The snapshot is written to a temporary file and renamed over the previous
+/// one, so a crash leaves either the old or the new snapshot in place. Every
+/// record is logged with a sequence number and the snapshot keeps the last one
+/// it covers, so records still in the log after a crash, or appended while the
+/// snapshot was written, are never replayed twice nor dropped.
+pub struct FileStorage {
+    dir: PathBuf,
+    wal: Mutex<Wal>,
+    /// Same file as `wal`, synced without holding up appends.
+    wal_sync: File,
+    /// Sequence number of the last snapshot stored, held while one is written.
+    snapshotted: Mutex<u64>,
+}
+
+/// The log being appended to.
+struct Wal {
+    file: File,
+    /// Length up to the last complete record.
+    len: u64,
+    /// Sequence number of the last record appended.
+    seq: u64,
+}
+
+/// A line of the log.
+#[derive(Serialize, Deserialize)]
+struct Entry<R> {
+    seq: u64,
+    record: R,
+}
+
+fn internal(e: impl ToString) -> ErrorResponse {
+    ErrorResponse::Internal(e.to_string())
+}
+
+impl FileStorage {
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, ErrorResponse> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir).map_err(internal)?;
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(WAL_FILE))
+            .map_err(internal)?;
+        let wal_sync = file.try_clone().map_err(internal)?;
+
+        Ok(FileStorage {
+            dir,
+            wal: Mutex::new(Wal {
+                len: file.metadata().map_err(internal)?.len(),
+                file,
+                seq: 0,
+            }),
+            wal_sync,
+            snapshotted: Mutex::new(0),
+        })
+    }
+
+    fn wal(&self) -> Result<MutexGuard<'_, Wal>, ErrorResponse> {
+        self.wal.lock().map_err(internal)
+    }
+}
+
+impl Storage for FileStorage {
+    fn append(&self, record: &Record) -> Result<(), ErrorResponse> {
+        let mut wal = self.wal()?;
+        let seq = wal.seq + 1;
+        let mut line = serde_json::to_vec(&Entry { seq, record }).map_err(internal)?;
+        line.push(b'\n');
+
+        /* Don't leave half a record for the next one to be glued to */
+        if let Err(e) = wal.file.write_all(&line) {
+            let _ = wal.file.set_len(wal.len);
+            return Err(internal(e));
+        }
+        wal.len += line.len() as u64;
+        wal.seq = seq;
+
+        Ok(())
+    }
+
+    fn last_seq(&self) -> Result<u64, ErrorResponse> {
+        Ok(self.wal()?.seq)
+    }
+
+    fn sync(&self) -> Result<(), ErrorResponse> {
+        self.wal_sync.sync_data().map_err(internal)
+    }
+
+    fn snapshot(&self, snapshot: &Snapshot) -> Result<(), ErrorResponse> {
+        let mut snapshotted = self.snapshotted.lock().map_err(internal)?;
+        /* A checkpoint racing with this one stored a newer snapshot already */
+        if snapshot.seq < *snapshotted {
+            return Ok(());
+        }
+
+        let contents = serde_json::to_vec(snapshot).map_err(internal)?;
+        let tmp = self.dir.join(format!("{}.tmp", SNAPSHOT_FILE));
+        let mut file = File::create(&tmp).map_err(internal)?;
+        file.write_all(&contents).map_err(internal)?;
+        file.sync_all().map_err(internal)?;
+        fs::rename(&tmp, self.dir.join(SNAPSHOT_FILE)).map_err(internal)?;
+        /* The rename has to be on disk before the log it replaces is gone */
+        File::open(&self.dir)
+            .and_then(|dir| dir.sync_all())
+            .map_err(internal)?;
+        *snapshotted = snapshot.seq;
+
+        /* Records appended meanwhile keep the log, the ones covered are skipped on load */
+        {
+            let mut wal = self.wal()?;
+            if wal.seq != snapshot.seq {
+                return Ok(());
+            }
+            wal.file.set_len(0).map_err(internal)?;
+            wal.len = 0;
+        }
+        self.wal_sync.sync_all().map_err(internal)
+    }
+
+    fn load(&self) -> Result<(Option<Snapshot>, Vec<Record>), ErrorResponse> {
+        let snapshot: Option<Snapshot> = match fs::read(self.dir.join(SNAPSHOT_FILE)) {
+            Ok(contents) => Some(serde_json::from_slice(&contents).map_err(internal)?),
+            Err(e) if e.kind() == ErrorKind::NotFound => None,
+            Err(e) => return Err(internal(e)),
+        };
+        let covered = snapshot.as_ref().map_or(0, |snapshot| snapshot.seq);
+
+        let mut reader = BufReader::new(File::open(self.dir.join(WAL_FILE)).map_err(internal)?);
+        let mut records: Vec<Record> = Vec::new();
+        let mut wal = self.wal()?;
+        wal.seq = covered;
+        wal.len = 0;
+        let mut line = Vec::new();
+        for number in 1.. {
+            line.clear();
+            let read = reader.read_until(b'\n', &mut line).map_err(internal)?;
+            if read == 0 {
+                break;
+            }
+
+            /* Only the last line can lack its newline, a crash mid-append tore it */
+            if line.last() != Some(&b'\n') {
+                tracing::warn!(file = WAL_FILE, line = number, "dropping torn log tail");
+                wal.file.set_len(wal.len).map_err(internal)?;
+                wal.file.sync_all().map_err(internal)?;
+                break;
+            }
+
+            /* Anything else unreadable is corruption, dropping it would lose what follows */
+            match serde_json::from_slice::<Entry<Record>>(&line) {
+                Ok(entry) => {
+                    if entry.seq > covered {
+                        records.push(entry.record);
+                    }
+                    wal.seq = wal.seq.max(entry.seq);
+                    wal.len += read as u64;
+                }
+                Err(e) => {
+                    return Err(ErrorResponse::Internal(format!(
+                        "{} line {}: {}",
+                        WAL_FILE, number, e
+                    )))
+                }
+            }
+        }
+        *self.snapshotted.lock().map_err(internal)? = covered;
+
+        Ok((snapshot, records))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::OpenOptions;
+
+    fn deleted(namespace: &str) -> Record {
+        Record::NamespaceDeleted {
+            namespace: namespace.to_string(),
+        }
+    }
+
+    fn namespaces(records: &[Record]) -> Vec<&str> {
+        records
+            .iter()
+            .map(|record| match record {
+                Record::NamespaceDeleted { namespace } => namespace.as_str(),
+                _ => panic!("unexpected record {:?}", record),
+            })
+            .collect()
+    }
+
+    fn reopened(dir: &Path) -> (FileStorage, Option<Snapshot>, Vec<Record>) {
+        let storage = FileStorage::open(dir).unwrap();
+        let (snapshot, records) = storage.load().unwrap();
+        (storage, snapshot, records)
+    }
+
+    fn write_raw(dir: &Path, bytes: &[u8]) {
+        let mut wal = OpenOptions::new()
+            .append(true)
+            .open(dir.join(WAL_FILE))
+            .unwrap();
+        wal.write_all(bytes).unwrap();
+    }
+
+    #[test]
+    fn replays_records_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let (storage, _, _) = reopened(dir.path());
+        for namespace in ["a", "b", "c"] {
+            storage.append(&deleted(namespace)).unwrap();
+        }
+        storage.sync().unwrap();
+
+        let (_, snapshot, records) = reopened(dir.path());
+        assert!(snapshot.is_none());
+        assert_eq!(namespaces(&records), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn drops_a_torn_last_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let (storage, _, _) = reopened(dir.path());
+        storage.append(&deleted("a")).unwrap();
+        write_raw(dir.path(), br#"{"seq":2,"record":{"NamespaceDel"#);
+
+        let (storage, _, records) = reopened(dir.path());
+        assert_eq!(namespaces(&records), vec!["a"]);
+
+        /* Appends after the cut start on a line of their own */
+        storage.append(&deleted("b")).unwrap();
+        let (_, _, records) = reopened(dir.path());
+        assert_eq!(namespaces(&records), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn fails_on_a_corrupt_line_in_the_middle() {
+        let dir = tempfile::tempdir().unwrap();
+        let (storage, _, _) = reopened(dir.path());
+        storage.append(&deleted("a")).unwrap();
+        write_raw(dir.path(), b"{\"seq\":2,\"record\":{\"Unknown\":{}}}\n");
+        storage.append(&deleted("c")).unwrap();
+        let len = fs::metadata(dir.path().join(WAL_FILE)).unwrap().len();
+
+        let storage = FileStorage::open(dir.path()).unwrap();
+        match storage.load() {
+            Err(e) => assert!(e.to_string().contains("horbo.wal line 2"), "{}", e),
+            Ok(_) => panic!("corrupt log was loaded"),
+        }
+        assert_eq!(fs::metadata(dir.path().join(WAL_FILE)).unwrap().len(), len);
+    }
+
+    #[test]
+    fn snapshot_truncates_the_records_it_covers() {
+        let dir = tempfile::tempdir().unwrap();
+        let (storage, _, _) = reopened(dir.path());
+        storage.append(&deleted("a")).unwrap();
+        storage.append(&deleted("b")).unwrap();
+        storage
+            .snapshot(&Snapshot {
+                seq: storage.last_seq().unwrap(),
+                deleted: vec!["a".to_string(), "b".to_string()],
+                ..Snapshot::default()
+            })
+            .unwrap();
+        assert_eq!(fs::metadata(dir.path().join(WAL_FILE)).unwrap().len(), 0);
+
+        /* Numbering carries on from the snapshot, or the next record would be skipped */
+        let (storage, snapshot, records) = reopened(dir.path());
+        assert_eq!(snapshot.unwrap().deleted, vec!["a", "b"]);
+        assert!(records.is_empty());
+        storage.append(&deleted("c")).unwrap();
+
+        let (_, _, records) = reopened(dir.path());
+        assert_eq!(namespaces(&records), vec!["c"]);
+    }
+
+    #[test]
+    fn keeps_records_appended_while_a_snapshot_is_written() {
+        let dir = tempfile::tempdir().unwrap();
+        let (storage, _, _) = reopened(dir.path());
+        storage.append(&deleted("a")).unwrap();
+        let seq = storage.last_seq().unwrap();
+        storage.append(&deleted("b")).unwrap();
+        storage
+            .snapshot(&Snapshot {
+                seq,
+                ..Snapshot::default()
+            })
+            .unwrap();
+
+        let (_, _, records) = reopened(dir.path());
+        assert_eq!(namespaces(&records), vec!["b"]);
+    }
+
+    #[test]
+    fn an_older_snapshot_never_replaces_a_newer_one() {
+        let dir = tempfile::tempdir().unwrap();
+        let (storage, _, _) = reopened(dir.path());
+        storage.append(&deleted("a")).unwrap();
+        storage.append(&deleted("b")).unwrap();
+        for seq in [2, 1] {
+            storage
+                .snapshot(&Snapshot {
+                    seq,
+                    ..Snapshot::default()
+                })
+                .unwrap();
+        }
+
+        let (_, snapshot, records) = reopened(dir.path());
+        assert_eq!(snapshot.unwrap().seq, 2);
+        assert!(records.is_empty());
+    }
+}
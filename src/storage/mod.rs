@@ -0,0 +1,3 @@
+pub mod file;
+#[allow(clippy::module_inception)]
+pub mod storage;
@@ -0,0 +1,80 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::common::error::ErrorResponse;
+use crate::core::schema::NamespaceDefinition;
+
+/// A registered node as persisted, ring positions are recomputed on recovery.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeRecord {
+    pub service_id: String,
+    pub ip: String,
+    pub port: u32,
+    pub healthy: bool,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub metadata: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+}
+
+/// A namespace and the nodes registered into it at the time of a snapshot.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NamespaceRecord {
+    pub namespace: String,
+    pub definition: NamespaceDefinition,
+    pub nodes: Vec<NodeRecord>,
+}
+
+/// Full registry state, everything logged before it is already folded in.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Sequence number of the last record folded in, see `Storage::last_seq`.
+    #[serde(default)]
+    pub seq: u64,
+    pub namespaces: Vec<NamespaceRecord>,
+    /// Namespaces of `horbo.yml` deleted at runtime, so they don't come back from
+    /// the config on the next start.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deleted: Vec<String>,
+}
+
+/// A single registry change as written to the log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Record {
+    NamespaceCreated {
+        namespace: String,
+        definition: NamespaceDefinition,
+    },
+    NamespaceDeleted {
+        namespace: String,
+    },
+    NodeRegistered {
+        namespace: String,
+        node: NodeRecord,
+    },
+    NodeDeregistered {
+        namespace: String,
+        service_id: String,
+    },
+    NodeHealth {
+        namespace: String,
+        service_id: String,
+        healthy: bool,
+    },
+}
+
+pub trait Storage: Send + Sync {
+    /// Appends a change under the next sequence number, called after it's been
+    /// applied in memory. It's only durable once `sync` returns.
+    fn append(&self, record: &Record) -> Result<(), ErrorResponse>;
+    /// Sequence number of the last change appended, the ones a snapshot built
+    /// right now covers.
+    fn last_seq(&self) -> Result<u64, ErrorResponse>;
+    /// Blocks until every change appended so far is on disk.
+    fn sync(&self) -> Result<(), ErrorResponse>;
+    /// Replaces the stored snapshot, unless a newer one was stored meanwhile.
+    /// Changes appended after `snapshot.seq` are kept.
+    fn snapshot(&self, snapshot: &Snapshot) -> Result<(), ErrorResponse>;
+    /// The last snapshot, if any, and the records appended after it in order.
+    fn load(&self) -> Result<(Option<Snapshot>, Vec<Record>), ErrorResponse>;
+}
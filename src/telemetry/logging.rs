@@ -0,0 +1,94 @@
+use std::future::Future;
+use std::io::{self, IsTerminal};
+use std::net::SocketAddr;
+
+use tonic::{Code, Response, Status};
+use tracing::{field, Instrument, Span};
+use tracing_subscriber::EnvFilter;
+
+use crate::core::schema::{LogFormat, LoggingDefinition};
+
+/// Target of the audit trail, health transitions and failure reports, so it can
+/// be filtered apart from operational logs, e.g. `warn,audit=info`.
+pub const AUDIT: &str = "audit";
+
+/// Installs the process wide subscriber, only the first call has any effect.
+pub fn init(definition: &LoggingDefinition) {
+    /* The level is validated with the rest of the config */
+    let filter = EnvFilter::try_new(&definition.level).unwrap_or_else(|_| EnvFilter::new("info"));
+    /* Colors only make sense on a terminal, not in a file or a log collector */
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_ansi(io::stdout().is_terminal());
+
+    let _ = match definition.format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Json => builder
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(false)
+            .try_init(),
+    };
+}
+
+/// Span of a single gRPC call, `service_id` is filled in by handlers that only
+/// learn it along the way and `outcome` once the call returns.
+///
+/// Created at the error level so its fields stay attached to warnings and errors
+/// when the configured level filters out the rest.
+pub fn rpc_span(
+    method: &'static str,
+    namespace: &str,
+    service_id: &str,
+    peer: Option<SocketAddr>,
+) -> Span {
+    let span = tracing::error_span!(
+        "rpc",
+        method,
+        namespace,
+        peer = field::Empty,
+        service_id = field::Empty,
+        outcome = field::Empty,
+    );
+    if let Some(peer) = peer {
+        span.record("peer", field::display(peer));
+    }
+    if !service_id.is_empty() {
+        span.record("service_id", service_id);
+    }
+
+    span
+}
+
+/// Runs a gRPC handler inside `span`, logging how it ended. Failures the caller
+/// can fix are warnings, the rest are errors.
+pub async fn traced<T, F>(span: Span, call: F) -> Result<Response<T>, Status>
+where
+    F: Future<Output = Result<Response<T>, Status>>,
+{
+    async move {
+        let result = call.await;
+        let span = Span::current();
+
+        match &result {
+            Ok(_) => {
+                span.record("outcome", "Ok");
+                tracing::debug!("handled");
+            }
+            Err(status) => {
+                span.record("outcome", field::debug(status.code()));
+                match status.code() {
+                    Code::Internal | Code::Unknown | Code::DataLoss => {
+                        tracing::error!(error = status.message(), "failed")
+                    }
+                    _ => tracing::warn!(error = status.message(), "rejected"),
+                }
+            }
+        }
+
+        result
+    }
+    .instrument(span)
+    .await
+}
@@ -0,0 +1,157 @@
+use std::future::Future;
+use std::sync::{LazyLock, LockResult};
+use std::time::Instant;
+
+use prometheus::core::Collector;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use tonic::{Code, Response, Status};
+
+use crate::common::error::ErrorResponse;
+use crate::core::application::service_discovery::ServiceDiscovery;
+
+/// Prefix of every metric name.
+const NAMESPACE: &str = "horbo";
+
+/// Uncontended locks are taken in well under a microsecond, anything near a
+/// second means writers are piling up.
+const LOCK_WAIT_BUCKETS: &[f64] = &[
+    0.000_001, 0.000_01, 0.000_1, 0.000_5, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0,
+];
+
+/// Metrics updated as calls come in, namespace gauges are read off the rings on
+/// every scrape instead so deleted namespaces don't linger.
+struct Metrics {
+    registry: Registry,
+    rpc_requests: IntCounterVec,
+    rpc_duration: HistogramVec,
+    lock_wait: HistogramVec,
+}
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+impl Metrics {
+    fn new() -> Self {
+        let rpc_requests = IntCounterVec::new(
+            Opts::new(
+                "rpc_requests_total",
+                "gRPC calls handled, by method and status code",
+            )
+            .namespace(NAMESPACE),
+            &["method", "code"],
+        )
+        .expect("valid rpc_requests_total metric");
+        let rpc_duration = HistogramVec::new(
+            HistogramOpts::new("rpc_duration_seconds", "Time spent handling gRPC calls")
+                .namespace(NAMESPACE),
+            &["method"],
+        )
+        .expect("valid rpc_duration_seconds metric");
+        let lock_wait = HistogramVec::new(
+            HistogramOpts::new(
+                "lock_wait_seconds",
+                "Time spent waiting on registry locks before holding them",
+            )
+            .namespace(NAMESPACE)
+            .buckets(LOCK_WAIT_BUCKETS.to_vec()),
+            &["lock"],
+        )
+        .expect("valid lock_wait_seconds metric");
+
+        let registry = Registry::new();
+        for collector in [
+            Box::new(rpc_requests.clone()) as Box<dyn Collector>,
+            Box::new(rpc_duration.clone()),
+            Box::new(lock_wait.clone()),
+        ] {
+            registry
+                .register(collector)
+                .expect("metric names are unique");
+        }
+
+        Metrics {
+            registry,
+            rpc_requests,
+            rpc_duration,
+            lock_wait,
+        }
+    }
+}
+
+/// Runs a gRPC handler, counting it by status code and timing it.
+pub async fn observe<T, F>(method: &'static str, call: F) -> Result<Response<T>, Status>
+where
+    F: Future<Output = Result<Response<T>, Status>>,
+{
+    let start = Instant::now();
+    let result = call.await;
+
+    let code = match &result {
+        Ok(_) => Code::Ok,
+        Err(status) => status.code(),
+    };
+    METRICS
+        .rpc_duration
+        .with_label_values(&[method])
+        .observe(start.elapsed().as_secs_f64());
+    METRICS
+        .rpc_requests
+        .with_label_values(&[method, &format!("{:?}", code)])
+        .inc();
+
+    result
+}
+
+/// Takes a lock, recording how long it took under `lock`.
+pub fn acquire<G>(
+    lock: &'static str,
+    take: impl FnOnce() -> LockResult<G>,
+) -> Result<G, ErrorResponse> {
+    let start = Instant::now();
+    let guard = take().map_err(|e| ErrorResponse::Internal(e.to_string()))?;
+    METRICS
+        .lock_wait
+        .with_label_values(&[lock])
+        .observe(start.elapsed().as_secs_f64());
+
+    Ok(guard)
+}
+
+/// Every metric in the Prometheus text format.
+pub fn render(service: &ServiceDiscovery) -> Result<String, ErrorResponse> {
+    let nodes = IntGaugeVec::new(
+        Opts::new("nodes", "Nodes registered in a namespace, by health").namespace(NAMESPACE),
+        &["namespace", "health"],
+    )
+    .map_err(|e| ErrorResponse::Internal(e.to_string()))?;
+    let ring_points = IntGaugeVec::new(
+        Opts::new("ring_points", "Points on a namespace ring, healthy or not").namespace(NAMESPACE),
+        &["namespace"],
+    )
+    .map_err(|e| ErrorResponse::Internal(e.to_string()))?;
+
+    for (namespace, ring) in service.service_map.load().iter() {
+        let total = ring.server_count() as i64;
+        let healthy = ring.healthy_server_count() as i64;
+        nodes
+            .with_label_values(&[namespace.as_str(), "healthy"])
+            .set(healthy);
+        nodes
+            .with_label_values(&[namespace.as_str(), "unhealthy"])
+            .set(total - healthy);
+        ring_points
+            .with_label_values(&[namespace.as_str()])
+            .set(ring.point_count() as i64);
+    }
+
+    let mut families = METRICS.registry.gather();
+    families.extend(nodes.collect());
+    families.extend(ring_points.collect());
+
+    let mut buffer = Vec::new();
+    TextEncoder::new()
+        .encode(&families, &mut buffer)
+        .map_err(|e| ErrorResponse::Internal(e.to_string()))?;
+    String::from_utf8(buffer).map_err(|e| ErrorResponse::Internal(e.to_string()))
+}
@@ -0,0 +1,200 @@
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::rustls::pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{RootCertStore, ServerConfig};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::core::schema::{TlsDefinition, TlsMode};
+
+/// How often certificate files are checked for changes.
+pub const RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+/// Connections that haven't finished the handshake by then are dropped.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Server TLS settings that can be swapped while serving.
+///
+/// Every accepted connection handshakes with the settings current at that
+/// moment, so a reload only applies to new connections and never drops the
+/// ones already established.
+pub struct ReloadableTls {
+    definition: TlsDefinition,
+    config: RwLock<Arc<ServerConfig>>,
+    /// Modification time of every file the current config was built from.
+    loaded_at: Mutex<Vec<Option<SystemTime>>>,
+}
+
+fn invalid(path: &str, reason: impl ToString) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}: {}", path, reason.to_string()),
+    )
+}
+
+fn read(path: &str) -> Result<Vec<u8>, io::Error> {
+    fs::read(path).map_err(|e| io::Error::new(e.kind(), format!("can't read {}: {}", path, e)))
+}
+
+fn certificates(path: &str) -> Result<Vec<CertificateDer<'static>>, io::Error> {
+    let certs = CertificateDer::pem_slice_iter(&read(path)?)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| invalid(path, e))?;
+    if certs.is_empty() {
+        return Err(invalid(path, "no certificate found"));
+    }
+
+    Ok(certs)
+}
+
+/// Builds the rustls settings of a TLS mode, mTLS requires a client certificate
+/// signed by `client_ca`.
+fn server_config(tls: &TlsDefinition) -> Result<ServerConfig, io::Error> {
+    let builder = ServerConfig::builder();
+    let builder = match tls.mode {
+        TlsMode::Mtls => {
+            let mut roots = RootCertStore::empty();
+            roots.add_parsable_certificates(certificates(&tls.client_ca)?);
+            let verifier = WebPkiClientVerifier::builder(roots.into())
+                .build()
+                .map_err(|e| invalid(&tls.client_ca, e))?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        _ => builder.with_no_client_auth(),
+    };
+
+    let key = PrivateKeyDer::from_pem_slice(&read(&tls.key)?).map_err(|e| invalid(&tls.key, e))?;
+    let mut config = builder
+        .with_single_cert(certificates(&tls.cert)?, key)
+        .map_err(|e| invalid(&tls.cert, e))?;
+    config.alpn_protocols = vec![b"h2".to_vec()];
+
+    Ok(config)
+}
+
+impl ReloadableTls {
+    pub fn load(definition: &TlsDefinition) -> Result<Self, io::Error> {
+        let loaded_at = Self::modified(definition);
+        let config = server_config(definition)?;
+
+        Ok(ReloadableTls {
+            definition: definition.clone(),
+            config: RwLock::new(Arc::new(config)),
+            loaded_at: Mutex::new(loaded_at),
+        })
+    }
+
+    fn files(definition: &TlsDefinition) -> Vec<&str> {
+        match definition.mode {
+            TlsMode::Mtls => vec![&definition.cert, &definition.key, &definition.client_ca],
+            _ => vec![&definition.cert, &definition.key],
+        }
+    }
+
+    /// Follows symlinks, so swapping a mounted secret directory counts as a change.
+    fn modified(definition: &TlsDefinition) -> Vec<Option<SystemTime>> {
+        Self::files(definition)
+            .into_iter()
+            .map(|path| fs::metadata(path).and_then(|meta| meta.modified()).ok())
+            .collect()
+    }
+
+    pub fn current(&self) -> Arc<ServerConfig> {
+        match self.config.read() {
+            Ok(config) => config.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    /// Rebuilds the settings if any file changed since the last load. A file
+    /// caught halfway through being rewritten fails to parse, the current
+    /// settings are kept then and the reload is retried on the next call.
+    pub fn reload_if_changed(&self) -> Result<bool, io::Error> {
+        let modified = Self::modified(&self.definition);
+        let mut loaded_at = self
+            .loaded_at
+            .lock()
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        if *loaded_at == modified {
+            return Ok(false);
+        }
+
+        let config = server_config(&self.definition)?;
+        match self.config.write() {
+            Ok(mut current) => *current = Arc::new(config),
+            Err(poisoned) => *poisoned.into_inner() = Arc::new(config),
+        }
+        *loaded_at = modified;
+
+        Ok(true)
+    }
+}
+
+/// Background certificate watcher, runs until the runtime stops.
+pub async fn watch(tls: Arc<ReloadableTls>, every: Duration) {
+    let mut interval = tokio::time::interval(every);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+
+        match tls.reload_if_changed() {
+            Ok(true) => tracing::info!("reloaded TLS certificates"),
+            Ok(false) => {}
+            Err(e) => tracing::error!(
+                error = %e,
+                "failed to reload TLS certificates, keeping the current ones"
+            ),
+        }
+    }
+}
+
+/// Accepts TCP connections and hands them to tonic once the TLS handshake is
+/// done. Handshakes run concurrently so a slow client can't hold up others.
+pub fn incoming(
+    listener: TcpListener,
+    tls: Arc<ReloadableTls>,
+) -> ReceiverStream<Result<TlsStream<TcpStream>, io::Error>> {
+    let (tx, rx) = mpsc::channel(128);
+
+    tokio::spawn(async move {
+        loop {
+            /* The server is gone, e.g. shutting down, stop listening right away */
+            let accepted = tokio::select! {
+                _ = tx.closed() => break,
+                accepted = listener.accept() => accepted,
+            };
+            let (stream, peer): (TcpStream, SocketAddr) = match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    /* e.g. out of file descriptors, back off instead of spinning */
+                    tracing::error!(error = %e, "failed to accept connection");
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            };
+            let _ = stream.set_nodelay(true);
+
+            let acceptor = TlsAcceptor::from(tls.current());
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => {
+                        let _ = tx.send(Ok(stream)).await;
+                    }
+                    Ok(Err(e)) => tracing::warn!(%peer, error = %e, "TLS handshake failed"),
+                    Err(_) => tracing::warn!(%peer, "TLS handshake timed out"),
+                }
+            });
+        }
+    });
+
+    ReceiverStream::new(rx)
+}
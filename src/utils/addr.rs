@@ -0,0 +1,67 @@
+/// Splits `host`, `host:port`, `[ipv6]` or `[ipv6]:port` into its host and port,
+/// `0` standing for no port. A bare IPv6 address carries no port, its colons
+/// aren't separators. `None` if the port isn't a valid one.
+pub fn split_host_port(addr: &str) -> Option<(&str, u32)> {
+    if let Some(bracketed) = addr.strip_prefix('[') {
+        let (host, rest) = bracketed.split_once(']')?;
+        return match rest {
+            "" => Some((host, 0)),
+            _ => Some((host, parse_port(rest.strip_prefix(':')?)?)),
+        };
+    }
+
+    match addr.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') => Some((host, parse_port(port)?)),
+        _ => Some((addr, 0)),
+    }
+}
+
+fn parse_port(port: &str) -> Option<u32> {
+    port.parse::<u16>().ok().map(u32::from)
+}
+
+/// Host part of an address that may carry a port, e.g. `payment-1.internal:8080` -> `payment-1.internal`.
+pub fn host_of(addr: &str) -> String {
+    match split_host_port(addr) {
+        Some((host, _)) => host.to_string(),
+        None => addr.to_string(),
+    }
+}
+
+/// Port of an address that may carry one, `0` otherwise.
+pub fn port_of(addr: &str) -> u32 {
+    match split_host_port(addr) {
+        Some((_, port)) => port,
+        None => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_hostnames_and_ip_literals() {
+        assert_eq!(
+            split_host_port("payment-1.internal:8080"),
+            Some(("payment-1.internal", 8080))
+        );
+        assert_eq!(
+            split_host_port("payment-1.internal"),
+            Some(("payment-1.internal", 0))
+        );
+        assert_eq!(split_host_port("10.0.0.1:5000"), Some(("10.0.0.1", 5000)));
+        assert_eq!(split_host_port("[::1]:50051"), Some(("::1", 50051)));
+        assert_eq!(split_host_port("[fe80::1]"), Some(("fe80::1", 0)));
+        assert_eq!(split_host_port("fe80::1"), Some(("fe80::1", 0)));
+    }
+
+    #[test]
+    fn rejects_invalid_ports() {
+        assert_eq!(split_host_port("payment-1.internal:http"), None);
+        assert_eq!(split_host_port("10.0.0.1:70000"), None);
+        assert_eq!(split_host_port("[::1]50051"), None);
+        assert_eq!(split_host_port("[::1"), None);
+        assert_eq!(port_of("payment-1.internal:http"), 0);
+    }
+}
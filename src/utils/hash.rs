@@ -5,4 +5,15 @@ pub fn ip_to_hash(ip: &str) -> u32 {
     let mut hasher = XxHash64::with_seed(0);
     ip.hash(&mut hasher);
     (hasher.finish() & 0x00FF_FFFF) as u32
-}
\ No newline at end of file
+}
+
+/// Hash of the `replica`-th virtual node of a server. Replica 0 keeps the
+/// plain address hash so a ring with a single virtual node per server
+/// places points exactly where it used to.
+pub fn vnode_hash(ip: &str, replica: u32) -> u32 {
+    if replica == 0 {
+        return ip_to_hash(ip);
+    }
+
+    ip_to_hash(&format!("{}#{}", ip, replica))
+}
@@ -1,2 +1,3 @@
+pub mod addr;
 pub mod hash;
 pub mod sort;
\ No newline at end of file